@@ -1,11 +1,17 @@
-use eframe::egui::{self, Id, ScrollArea, Sense, TextEdit, Vec2b};
-use crate::{parser::{parse_page, Page}, lua::Executer};
+use std::{cell::RefCell, collections::HashMap, path::{Path, PathBuf}, rc::Rc};
+use eframe::egui::{self, DragValue, FontData, FontDefinitions, FontFamily, Id, ScrollArea, Sense, TextEdit, Vec2b};
+use crate::{console::{show_entry, show_source, SourceView}, parser::{parse_page, Page}, keys::VIEWER_SHORTCUTS, lua::{Executer, PageEvent}, sandbox::Permission, scripts::Script, theme::ThemeChoice};
 
 pub struct App {
     file_text: String,
-    page: anyhow::Result<Page>,
+    page: anyhow::Result<Rc<RefCell<Page>>>,
     executer: Executer,
     show_console: bool,
+    theme: ThemeChoice,
+    fonts: FontDefinitions,
+    permission_answers: HashMap<PathBuf, Vec<(Permission, bool)>>,
+    asked_permissions: Vec<Permission>,
+    source_view: Option<SourceView>,
 }
 
 impl Default for App {
@@ -15,6 +21,11 @@ impl Default for App {
             page: Err(anyhow::anyhow!("Enter file path")),
             executer: Executer::new(),
             show_console: false,
+            theme: ThemeChoice::Page,
+            fonts: FontDefinitions::default(),
+            permission_answers: HashMap::new(),
+            asked_permissions: vec![],
+            source_view: None,
         }
     }
 }
@@ -24,42 +35,145 @@ impl App {
         Default::default()
     }
 
-    fn load_page(&mut self) {
-        self.page = parse_page(&self.file_text);
+    fn navigate(&mut self, ctx: &egui::Context, location: Option<String>) {
+        if self.page.is_ok() && self.executer.fire_page_event(PageEvent::Unload(location.clone())) {
+            return;
+        }
+        if let Some(location) = location {
+            self.file_text = location;
+        }
+        self.load_page(ctx);
+    }
+
+    fn page_key(&self) -> PathBuf {
+        Path::new(&self.file_text).canonicalize().unwrap_or_else(|_| PathBuf::from(&self.file_text))
+    }
+
+    fn answer_permissions(&mut self, ctx: &egui::Context, allow: bool) {
+        let answers = self.permission_answers.entry(self.page_key()).or_default();
+        answers.extend(self.asked_permissions.drain(..).map(|permission| (permission, allow)));
+        if allow {
+            self.load_page(ctx);
+        }
+    }
+
+    fn load_page(&mut self, ctx: &egui::Context) {
+        self.page = parse_page(&self.file_text).map(|page| Rc::new(RefCell::new(page)));
         self.executer.console.clear();
+        self.source_view = None;
+        let mut fonts = FontDefinitions::default();
         if let Ok(page) = &self.page {
-            self.executer.init_lua();
-            for script in &page.scripts {
-                self.executer.try_run(&script, "script");
+            for (name, data) in &page.borrow().fonts {
+                fonts.font_data.insert(name.clone(), FontData::from_owned(data.clone()));
+                let mut family = vec![name.clone()];
+                family.extend(fonts.families[&FontFamily::Proportional].iter().cloned());
+                fonts.families.insert(FontFamily::Name(name.as_str().into()), family);
+                for fallback in [FontFamily::Proportional, FontFamily::Monospace] {
+                    fonts.families.get_mut(&fallback).unwrap().push(name.clone());
+                }
             }
         }
+        if fonts != self.fonts {
+            ctx.set_fonts(fonts.clone());
+            self.fonts = fonts;
+        }
+        self.asked_permissions.clear();
+        match &self.page {
+            Ok(page) => {
+                let answers = self.permission_answers.get(&self.page_key()).cloned().unwrap_or_default();
+                let mut granted = vec![];
+                for permission in page.borrow().permissions.iter().copied() {
+                    match answers.iter().find(|(answered, _)| *answered == permission) {
+                        Some((_, true)) => granted.push(permission),
+                        Some((_, false)) => {}
+                        None => self.asked_permissions.push(permission),
+                    }
+                }
+                self.executer.init_lua(page.clone(), granted);
+                let scripts = page.borrow().scripts.clone();
+                let dir = page.borrow().dir.clone();
+                for script in &scripts {
+                    match script {
+                        Script::Inline { code, name, line } => self.executer.try_run(code, name, *line),
+                        Script::File(src) => self.executer.run_file(&dir, src),
+                    }
+                }
+            }
+            Err(_) => self.executer.stop(),
+        }
     }
 }
 
 impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let system_visuals = frame.info().system_theme.unwrap_or(eframe::Theme::Dark).egui_visuals();
+        let visuals = match self.theme {
+            ThemeChoice::Page => self.page.as_ref().ok().and_then(|page| page.borrow().theme.clone()).unwrap_or(system_visuals),
+            ThemeChoice::System => system_visuals,
+            ThemeChoice::Light => egui::Visuals::light(),
+            ThemeChoice::Dark => egui::Visuals::dark(),
+        };
+        if ctx.style().visuals != visuals {
+            ctx.set_visuals(visuals);
+        }
+
+        let [reload, reload_command, console] = VIEWER_SHORTCUTS.map(|(shortcut, _)| shortcut);
+        if ctx.input_mut(|input| input.consume_shortcut(&reload) || input.consume_shortcut(&reload_command)) {
+            self.navigate(ctx, None);
+        }
+        if ctx.input_mut(|input| input.consume_shortcut(&console)) {
+            self.show_console = !self.show_console;
+        }
+        self.executer.run_key_bindings(ctx);
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 match &self.page {
                     Ok(page) => {
-                        ui.label(&page.title);
+                        ui.label(&page.borrow().title);
                     }
                     Err(_) => {
                         ui.label("Error");
                     }
                 }
                 if ui.button("Reload").clicked() {
-                    self.load_page();
+                    self.navigate(ctx, None);
                 }
+                ui.menu_button("Theme", |ui| {
+                    for choice in ThemeChoice::ALL {
+                        if ui.radio_value(&mut self.theme, choice, choice.name()).clicked() {
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.menu_button("Limits", |ui| {
+                    ui.add(DragValue::new(&mut self.executer.limits.time_ms).clamp_range(10..=60000).suffix(" ms per script run"));
+                    ui.add(DragValue::new(&mut self.executer.limits.memory_mb).clamp_range(1..=4096).suffix(" MB of memory"));
+                });
                 let response = ui.add(TextEdit::singleline(&mut self.file_text).hint_text("Enter path to file here...").desired_width(f32::INFINITY));
                 if response.lost_focus() && response.ctx.input(|state| state.key_pressed(egui::Key::Enter)) {
-                    self.load_page();
+                    self.navigate(ctx, Some(self.file_text.clone()));
                 }
             });
         });
 
+        if !self.asked_permissions.is_empty() {
+            egui::TopBottomPanel::top("permissions").show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let descriptions: Vec<&str> = self.asked_permissions.iter().map(|permission| permission.description()).collect();
+                    ui.label(format!("This page asks to {}.", descriptions.join(" and ")));
+                    if ui.button("Allow").clicked() {
+                        self.answer_permissions(ctx, true);
+                    }
+                    if ui.button("Deny").clicked() {
+                        self.answer_permissions(ctx, false);
+                    }
+                });
+            });
+        }
+
         if self.show_console {
-            egui::SidePanel::right("console").resizable(false).exact_width(150.0).show(ctx, |ui| {
+            egui::SidePanel::right("console").resizable(false).exact_width(220.0).show(ctx, |ui| {
                 ScrollArea::vertical().auto_shrink(Vec2b {x: false, y: false}).show(ui, |ui| {
                     ui.interact(ui.max_rect(), Id::new("bg_side"), Sense::click()).context_menu(|ui| {
                         if ui.button("Close console").clicked() {
@@ -67,14 +181,23 @@ impl eframe::App for App {
                             ui.close_menu();
                         }
                     });
-                    for message in &self.executer.console {
-                        ui.label(message);
+                    for entry in &self.executer.console {
+                        if let Some((path, line)) = show_entry(ui, entry) {
+                            self.source_view = Some(SourceView::open(path, line));
+                        }
                     }
                 });
             });
         }
 
+        if let Some(view) = &self.source_view {
+            if !show_source(ctx, view) {
+                self.source_view = None;
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            let page_size = ui.available_size();
             let mut location = None;
             let mut title = None;
             ScrollArea::both().auto_shrink(Vec2b {x: false, y: false}).show(ui, |ui| {
@@ -84,9 +207,10 @@ impl eframe::App for App {
                         ui.close_menu();
                     }
                 });
-                match &mut self.page {
+                match &self.page {
                     Ok(page) => {
-                        self.executer.update_document(page, &mut location, &mut title, ui.ctx());
+                        let mut page = page.borrow_mut();
+                        self.executer.update_document(&mut page, &mut location, &mut title, ui.ctx());
                         page.render(ui, &mut self.executer);
                     }
                     Err(why) => {
@@ -95,13 +219,19 @@ impl eframe::App for App {
                     }
                 }
             });
+            self.executer.run_timers(ctx);
+            self.executer.run_queued();
+            self.executer.render_dialogs(ctx);
+            if self.page.is_ok() {
+                self.executer.update_page_events(ctx, page_size);
+            }
+            self.executer.request_timer_repaint(ctx);
             if let Some(location) = location {
-                self.file_text = location;
-                self.load_page();
+                self.navigate(ctx, Some(location));
             }
             if let Some(title) = title {
-                if let Ok(page) = &mut self.page {
-                    page.title = title;
+                if let Ok(page) = &self.page {
+                    page.borrow_mut().title = title;
                 }
             }
         });
@@ -0,0 +1,109 @@
+use std::{fs, path::{Path, PathBuf}};
+use eframe::egui::{self, Color32, RichText, ScrollArea, TextStyle, Ui};
+use crate::sandbox::resolve_path;
+
+const SOURCE_CONTEXT: usize = 5;
+
+pub struct ConsoleEntry {
+    pub message: String,
+    pub traceback: Option<String>,
+    pub location: Option<(PathBuf, usize)>,
+    pub error: bool,
+}
+
+impl ConsoleEntry {
+    pub fn message(message: String) -> Self {
+        Self { message, traceback: None, location: None, error: false }
+    }
+
+    pub fn error(text: String, dir: &Path, source: &str) -> Self {
+        let (message, traceback) = match text.split_once("\nstack traceback:") {
+            Some((message, traceback)) => (message.trim_end(), Some(format!("stack traceback:{}", traceback.trim_end()))),
+            None => (text.trim_end(), None),
+        };
+        let message = message.trim_start_matches("runtime error: ").trim_start_matches("syntax error: ").to_string();
+        let location = find_location(&message, dir, source)
+            .or_else(|| traceback.iter().flat_map(|traceback| traceback.lines()).find_map(|line| find_location(line.trim(), dir, source)));
+        Self { message, traceback, location, error: true }
+    }
+}
+
+fn find_location(text: &str, dir: &Path, source: &str) -> Option<(PathBuf, usize)> {
+    let mut start = 0;
+    while let Some(offset) = text[start..].find(':') {
+        let colon = start + offset;
+        let digits: String = text[colon + 1..].chars().take_while(|chr| chr.is_ascii_digit()).collect();
+        if !digits.is_empty() && text[colon + 1 + digits.len()..].starts_with(':') {
+            let chunk = &text[..colon];
+            let line = digits.parse().ok()?;
+            if !source.is_empty() && chunk.starts_with(&format!("{}:", source)) {
+                return Some((dir.join(source), line));
+            }
+            return resolve_path(dir, chunk).ok().filter(|path| path.is_file()).map(|path| (path, line));
+        }
+        start = colon + 1;
+    }
+    None
+}
+
+pub fn show_entry(ui: &mut Ui, entry: &ConsoleEntry) -> Option<(PathBuf, usize)> {
+    let mut opened = None;
+    if !entry.error {
+        ui.label(&entry.message);
+        return None;
+    }
+    ui.label(RichText::new(&entry.message).color(ui.visuals().error_fg_color));
+    if let Some((path, line)) = &entry.location {
+        let name = path.file_name().map_or(path.to_string_lossy(), |name| name.to_string_lossy());
+        if ui.link(format!("{}:{}", name, line)).clicked() {
+            opened = Some((path.clone(), *line));
+        }
+    }
+    if let Some(traceback) = &entry.traceback {
+        ui.collapsing("Traceback", |ui| {
+            ui.label(RichText::new(traceback).monospace());
+        });
+    }
+    opened
+}
+
+pub struct SourceView {
+    path: PathBuf,
+    line: usize,
+    lines: Result<Vec<String>, String>,
+}
+
+impl SourceView {
+    pub fn open(path: PathBuf, line: usize) -> Self {
+        let lines = fs::read_to_string(&path).map(|text| text.lines().map(str::to_string).collect()).map_err(|why| why.to_string());
+        Self { path, line, lines }
+    }
+}
+
+pub fn show_source(ctx: &egui::Context, view: &SourceView) -> bool {
+    let mut open = true;
+    let name = view.path.file_name().map_or(view.path.to_string_lossy(), |name| name.to_string_lossy());
+    egui::Window::new(format!("{}:{}", name, view.line)).open(&mut open).collapsible(false).show(ctx, |ui| {
+        let lines = match &view.lines {
+            Ok(lines) => lines,
+            Err(why) => {
+                ui.label(format!("Could not read {}: {}", view.path.display(), why));
+                return;
+            }
+        };
+        let first = view.line.saturating_sub(SOURCE_CONTEXT).max(1);
+        let last = (view.line + SOURCE_CONTEXT).min(lines.len());
+        let width = last.to_string().len();
+        ScrollArea::horizontal().show(ui, |ui| {
+            for number in first..=last {
+                let text = RichText::new(format!("{:>width$} | {}", number, lines[number - 1])).text_style(TextStyle::Monospace);
+                if number == view.line {
+                    ui.label(text.color(ui.visuals().error_fg_color).background_color(Color32::from_black_alpha(40)));
+                } else {
+                    ui.label(text);
+                }
+            }
+        });
+    });
+    open
+}
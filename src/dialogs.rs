@@ -0,0 +1,162 @@
+use eframe::egui::{Align2, Area, Color32, Context, Frame, Id, Key, Order, RichText, Sense, TextEdit, Vec2, Window};
+use mlua::RegistryKey;
+
+pub enum DialogKind {
+    Alert,
+    Confirm,
+    Prompt(String),
+}
+
+pub struct Dialog {
+    pub message: String,
+    pub kind: DialogKind,
+    pub callback: Option<RegistryKey>,
+}
+
+pub enum DialogAnswer {
+    Ok,
+    Confirm(bool),
+    Prompt(Option<String>),
+}
+
+fn show_overlay(ctx: &Context) {
+    let screen = ctx.screen_rect();
+    let overlay = Area::new(Id::new("dialog_overlay")).order(Order::Middle).movable(false).fixed_pos(screen.min).show(ctx, |ui| {
+        let (rect, _) = ui.allocate_exact_size(screen.size(), Sense::click());
+        ui.painter().rect_filled(rect, 0.0, Color32::from_black_alpha(120));
+    });
+    ctx.move_to_top(overlay.response.layer_id);
+}
+
+pub fn show_unresponsive(ctx: &Context, time_ms: u64) -> Option<bool> {
+    show_overlay(ctx);
+    let mut stop = None;
+    let window = Window::new("Script is unresponsive")
+        .id(Id::new("unresponsive"))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label(format!("A script on this page ran for more than {} ms and was stopped. Stop all scripts on this page, or keep running it with twice the time for each script?", time_ms));
+            ui.horizontal(|ui| {
+                if ui.button("Stop scripts").clicked() {
+                    stop = Some(true);
+                }
+                if ui.button("Keep running the page").clicked() {
+                    stop = Some(false);
+                }
+            });
+        });
+    if let Some(window) = window {
+        ctx.move_to_top(window.response.layer_id);
+    }
+    stop
+}
+
+pub fn show_dialog(ctx: &Context, dialog: &mut Dialog) -> Option<DialogAnswer> {
+    show_overlay(ctx);
+
+    let mut answer = None;
+    let title = match dialog.kind {
+        DialogKind::Alert => "Alert",
+        DialogKind::Confirm => "Confirm",
+        DialogKind::Prompt(_) => "Prompt",
+    };
+    let window = Window::new(title)
+        .id(Id::new("dialog"))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label(&dialog.message);
+            let enter = ui.input(|input| input.key_pressed(Key::Enter));
+            let escape = ui.input(|input| input.key_pressed(Key::Escape));
+            if let DialogKind::Prompt(input) = &mut dialog.kind {
+                let response = ui.add(TextEdit::singleline(input).desired_width(f32::INFINITY));
+                if !response.has_focus() && !response.lost_focus() {
+                    response.request_focus();
+                }
+            }
+            ui.horizontal(|ui| {
+                let ok = ui.button("OK").clicked() || enter;
+                let cancel = !matches!(dialog.kind, DialogKind::Alert) && (ui.button("Cancel").clicked() || escape);
+                answer = match &dialog.kind {
+                    DialogKind::Alert if ok || escape => Some(DialogAnswer::Ok),
+                    DialogKind::Confirm if ok || cancel => Some(DialogAnswer::Confirm(ok)),
+                    DialogKind::Prompt(input) if ok => Some(DialogAnswer::Prompt(Some(input.clone()))),
+                    DialogKind::Prompt(_) if cancel => Some(DialogAnswer::Prompt(None)),
+                    _ => None,
+                };
+            });
+        });
+    if let Some(window) = window {
+        ctx.move_to_top(window.response.layer_id);
+    }
+    answer
+}
+
+#[derive(Clone, Copy)]
+pub enum ToastKind {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl ToastKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "info" => Self::Info,
+            "success" => Self::Success,
+            "warning" => Self::Warning,
+            "error" => Self::Error,
+            _ => return None,
+        })
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            Self::Info => Color32::from_rgb(60, 110, 200),
+            Self::Success => Color32::from_rgb(50, 150, 80),
+            Self::Warning => Color32::from_rgb(200, 140, 30),
+            Self::Error => Color32::from_rgb(190, 50, 50),
+        }
+    }
+}
+
+pub struct Toast {
+    pub message: String,
+    pub kind: ToastKind,
+    pub duration: f64,
+    pub expires: Option<f64>,
+}
+
+const TOAST_WIDTH: f32 = 220.0;
+
+pub fn show_toasts(ctx: &Context, toasts: &mut Vec<Toast>) {
+    let now = ctx.input(|input| input.time);
+    toasts.retain(|toast| toast.expires.is_none_or(|expires| expires > now));
+    let mut offset = -8.0;
+    let mut dismissed = None;
+    for (index, toast) in toasts.iter_mut().enumerate() {
+        let expires = *toast.expires.get_or_insert(now + toast.duration);
+        let area = Area::new(Id::new("toast").with(index))
+            .order(Order::Foreground)
+            .movable(false)
+            .anchor(Align2::RIGHT_BOTTOM, Vec2::new(-8.0, offset))
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).fill(toast.kind.color()).show(ui, |ui| {
+                    ui.set_width(TOAST_WIDTH);
+                    ui.label(RichText::new(&toast.message).color(Color32::WHITE));
+                });
+            });
+        if area.response.interact(Sense::click()).clicked() {
+            dismissed = Some(index);
+        }
+        offset -= area.response.rect.height() + 4.0;
+        ctx.request_repaint_after(std::time::Duration::from_secs_f64((expires - now).max(0.0)));
+    }
+    if let Some(index) = dismissed {
+        toasts.remove(index);
+    }
+}
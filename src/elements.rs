@@ -1,32 +1,41 @@
-use eframe::egui::Layout;
+use anyhow::anyhow;
+use eframe::egui::{Align, CollapsingHeader, Direction, FontId, Frame, Label, Layout, Rect, Response, RichText, ScrollArea, Sense, TextStyle, Vec2};
 
-use crate::{lua::Executer, parser::{Element, Elements, Style}};
+use crate::{highlight::highlight, lua::Executer, parser::{parse_offset, parse_size, Element, Elements, Errors, Node}, style::Style, text::{render_spans, Span}};
 
 pub struct Heading {
-    pub text: String,
+    pub spans: Vec<Span>,
 }
 
 impl Element for Heading {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, _: &mut Executer) {
-        ui.heading(&self.text);
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        render_spans(ui, &self.spans, style, TextStyle::Heading, executer)
     }
 
-    fn set_text(&mut self, text: String, _: &mut Executer) {
-        self.text = text;
+    fn get_text(&self) -> Option<String> {
+        Some(self.spans.iter().map(|span| span.text.as_str()).collect())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
+        self.spans = vec![Span::plain(text)];
     }
 }
 
 pub struct Paragraph {
-    pub text: String,
+    pub spans: Vec<Span>,
 }
 
 impl Element for Paragraph {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, _: &mut Executer) {
-        ui.label(&self.text);
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        render_spans(ui, &self.spans, style, TextStyle::Body, executer)
     }
 
-    fn set_text(&mut self, text: String, _: &mut Executer) {
-        self.text = text;
+    fn get_text(&self) -> Option<String> {
+        Some(self.spans.iter().map(|span| span.text.as_str()).collect())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
+        self.spans = vec![Span::plain(text)];
     }
 }
 
@@ -36,112 +45,295 @@ pub struct Button {
 }
 
 impl Element for Button {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, executer: &mut Executer) {
-        if ui.button(&self.text).clicked() {
-            executer.try_run(&self.on_click, "onclick");
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let button = eframe::egui::Button::new(style.rich_text(&self.text, ui, TextStyle::Button)).frame(!style.has_box());
+        let response = ui.add(button);
+        if response.clicked() {
+            executer.queue_script(&self.on_click, "onclick");
         }
+        response
     }
 
-    fn set_text(&mut self, text: String, _: &mut Executer) {
+    fn get_text(&self) -> Option<String> {
+        Some(self.text.clone())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
         self.text = text;
     }
 
-    fn set_attr(&mut self, attr: String, value: String, executer: &mut Executer) {
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "onclick" {
+            Some(self.on_click.clone())
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
         if attr == "onclick" {
             self.on_click = value;
         } else {
-            executer.log_error(format!("Unknown attribute '{}'", attr))
+            errors.log_error(format!("Unknown attribute '{}'", attr))
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scroll {
+    None,
+    Vertical,
+    Horizontal,
+    Both,
+}
+
+impl Scroll {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "none" => Self::None,
+            "vertical" => Self::Vertical,
+            "horizontal" => Self::Horizontal,
+            "both" => Self::Both,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Vertical => "vertical",
+            Self::Horizontal => "horizontal",
+            Self::Both => "both",
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+}
+
+impl Justify {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "start" => Self::Start,
+            "center" => Self::Center,
+            "end" => Self::End,
+            "space-between" => Self::SpaceBetween,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Center => "center",
+            Self::End => "end",
+            Self::SpaceBetween => "space-between",
+        }
+    }
+}
+
+fn size_name(size: Option<f32>) -> String {
+    match size {
+        Some(size) => size.to_string(),
+        None => "auto".to_string(),
+    }
+}
+
 pub struct Div {
     pub inner: Elements,
     pub layout: Option<Layout>,
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    pub max_height: Option<f32>,
+    pub scroll: Scroll,
+    pub wrap: bool,
+    pub gap: Option<f32>,
+    pub justify: Justify,
+    pub stretch: bool,
+    pub sizes: Vec<Vec2>,
 }
 
-impl Element for Div {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, style: Style, executer: &mut Executer) {
-        if let Some(layout) = self.layout {
-            ui.with_layout(layout, |ui| {
-                for element in &mut self.inner {
-                    element.render(ui, style, executer);
-                }
-            });
-        } else {
+impl Div {
+    fn is_flex(&self) -> bool {
+        self.wrap || self.gap.is_some() || self.justify != Justify::Start || self.stretch || self.inner.iter().any(|node| node.grow > 0.0)
+    }
+
+    fn render_inner(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let mut layout = self.layout.unwrap_or(*ui.layout());
+        if let Some(direction) = style.direction {
+            layout.main_dir = direction;
+        }
+        if let Some(align) = style.align {
+            layout.cross_align = align;
+        }
+        if self.is_flex() {
+            return self.render_flex(ui, layout, style, executer);
+        }
+        ui.with_layout(layout, |ui| {
             for element in &mut self.inner {
                 element.render(ui, style, executer);
             }
-        }
+        }).response
     }
 
-    fn set_inner(&mut self, new: Elements, _: &mut Executer) {
-        self.inner = new;
-    }
+    fn render_flex(&mut self, ui: &mut eframe::egui::Ui, layout: Layout, style: &Style, executer: &mut Executer) -> Response {
+        let horizontal = layout.main_dir.is_horizontal();
+        let reversed = matches!(layout.main_dir, Direction::RightToLeft | Direction::BottomUp);
+        let main = |size: Vec2| if horizontal { size.x } else { size.y };
+        let cross = |size: Vec2| if horizontal { size.y } else { size.x };
+        let vec = |main: f32, cross: f32| if horizontal { Vec2::new(main, cross) } else { Vec2::new(cross, main) };
+        let available = ui.available_rect_before_wrap();
+        let max_main = main(available.size());
+        let gap = self.gap.unwrap_or(main(ui.spacing().item_spacing));
+        let line_gap = self.gap.unwrap_or(cross(ui.spacing().item_spacing));
+        self.sizes.resize(self.inner.len(), Vec2::ZERO);
 
-    fn set_path_inner(&mut self, mut path: std::collections::VecDeque<usize>, new: Elements, executer: &mut Executer) {
-        match path.pop_front() {
-            Some(index) => {
-                match self.inner.get_mut(index) {
-                    Some(element) => {
-                        element.set_path_inner(path, new, executer);
-                    }
-                    None => {
-                        executer.log_error("Invalid path");
-                    }
-                }
-            }
-            None => {
-                self.set_inner(new, executer);
+        for (index, node) in self.inner.iter_mut().enumerate() {
+            if node.position.absolute {
+                node.render(&mut ui.child_ui(available, layout), style, executer);
+            } else if node.grow > 0.0 || self.stretch {
+                let mut measure = ui.child_ui(available, Layout::top_down(Align::Min));
+                measure.set_visible(false);
+                self.sizes[index] = node.render(&mut measure, style, executer).rect.size();
             }
         }
-    }
 
-    fn set_path_text(&mut self, mut path: std::collections::VecDeque<usize>, text: String, executer: &mut Executer) {
-        match path.pop_front() {
-            Some(index) => {
-                match self.inner.get_mut(index) {
-                    Some(element) => {
-                        if path.len() == 0 {
-                            element.set_text(text, executer);
-                        } else {
-                            element.set_path_text(path, text, executer);
-                        }
-                    }
-                    None => {
-                        executer.log_error("Invalid path");
-                    }
+        let mut lines: Vec<Vec<usize>> = vec![];
+        let mut line_main = 0.0;
+        for index in (0..self.inner.len()).filter(|&index| !self.inner[index].position.absolute) {
+            let size = main(self.sizes[index]);
+            match lines.last_mut() {
+                Some(line) if !self.wrap || line_main + gap + size <= max_main => {
+                    line.push(index);
+                    line_main += gap + size;
+                }
+                _ => {
+                    lines.push(vec![index]);
+                    line_main = size;
                 }
-            }
-            None => {
-                executer.log_error("Div cannot contain text");
             }
         }
-    }
+        let line_length = |line: &Vec<usize>| line.iter().map(|&index| main(self.sizes[index])).sum::<f32>() + gap * (line.len() - 1) as f32;
+        let lengths: Vec<f32> = lines.iter().map(line_length).collect();
+        let content_main = lengths.iter().copied().fold(0.0, f32::max);
+        let distributes = reversed || self.justify != Justify::Start || self.inner.iter().any(|node| node.grow > 0.0);
+        let container_main = if distributes && max_main.is_finite() { max_main.max(content_main) } else { content_main };
 
-    fn set_path_attr(&mut self, mut path: std::collections::VecDeque<usize>, attr: String, value: String, executer: &mut Executer) {
-        match path.pop_front() {
-            Some(index) => {
-                match self.inner.get_mut(index) {
-                    Some(element) => {
-                        if path.len() == 0 {
-                            element.set_attr(attr, value, executer);
-                        } else {
-                            element.set_path_attr(path, attr, value, executer);
-                        }
-                    }
-                    None => {
-                        executer.log_error("Invalid path");
-                    }
+        let mut cross_offset = 0.0;
+        for (line, length) in lines.iter().zip(lengths) {
+            let line_cross = line.iter().map(|&index| cross(self.sizes[index])).fold(0.0, f32::max);
+            let free = (container_main - length).max(0.0);
+            let total_grow: f32 = line.iter().map(|&index| self.inner[index].grow).sum();
+            let (mut offset, spacing) = match self.justify {
+                _ if total_grow > 0.0 => (0.0, gap),
+                Justify::Start => (0.0, gap),
+                Justify::Center => (free / 2.0, gap),
+                Justify::End => (free, gap),
+                Justify::SpaceBetween if line.len() > 1 => (0.0, gap + free / (line.len() - 1) as f32),
+                Justify::SpaceBetween => (0.0, gap),
+            };
+            for &index in line {
+                let node = &mut self.inner[index];
+                let size = self.sizes[index];
+                let grow = if total_grow > 0.0 { free * node.grow / total_grow } else { 0.0 };
+                let child_main = main(size) + grow;
+                let child_cross = if self.stretch { line_cross } else { cross(size) };
+                let cross_pos = cross_offset + match layout.cross_align {
+                    Align::Min => 0.0,
+                    Align::Center => (line_cross - child_cross) / 2.0,
+                    Align::Max => line_cross - child_cross,
+                };
+                let main_pos = if reversed { container_main - offset - child_main } else { offset };
+                let min = available.min + vec(main_pos, cross_pos);
+                let expands = node.grow > 0.0 || self.stretch;
+                let (max_rect, child_layout) = if expands {
+                    let justify = (if horizontal { self.stretch } else { node.grow > 0.0 }, if horizontal { node.grow > 0.0 } else { self.stretch });
+                    (Rect::from_min_size(min, vec(child_main, child_cross)), Layout::top_down(Align::Min).with_main_justify(justify.0).with_cross_justify(justify.1))
+                } else {
+                    (Rect::from_min_max(min, available.max.max(min)), Layout::top_down(Align::Min))
+                };
+                let mut child_ui = ui.child_ui(max_rect, child_layout);
+                let measured = node.render(&mut child_ui, style, executer).rect.size();
+                if !expands && measured != size {
+                    self.sizes[index] = measured;
+                    ui.ctx().request_repaint();
                 }
+                offset += child_main + spacing;
             }
-            None => {
-                self.set_attr(attr, value, executer);
-            }
+            cross_offset += line_cross + line_gap;
         }
+        let cross_total = (cross_offset - line_gap).max(0.0);
+        ui.allocate_rect(Rect::from_min_size(available.min, vec(container_main, cross_total)), Sense::hover())
     }
+}
 
-    fn set_attr(&mut self, attr: String, value: String, executer: &mut Executer) {
+impl Element for Div {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        if self.width.is_none() && self.height.is_none() && self.max_height.is_none() && self.scroll == Scroll::None {
+            return self.render_inner(ui, style, executer);
+        }
+        let enabled = match self.scroll {
+            Scroll::None => [false, false],
+            Scroll::Vertical => [false, true],
+            Scroll::Horizontal => [true, false],
+            Scroll::Both => [true, true],
+        };
+        let mut area = ScrollArea::new(enabled).id_source(ui.next_auto_id()).auto_shrink([false, self.height.is_none()]);
+        if let Some(width) = self.width {
+            area = area.min_scrolled_width(width).max_width(width);
+        }
+        if let Some(height) = self.height {
+            area = area.min_scrolled_height(height).max_height(height);
+        } else if let Some(max_height) = self.max_height {
+            area = area.max_height(max_height);
+        }
+        ui.scope(|ui| {
+            area.show(ui, |ui| {
+                self.render_inner(ui, style, executer);
+            });
+        }).response
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        let layout = self.layout.unwrap_or_default();
+        match attr {
+            "direction" => Some(match layout.main_dir {
+                eframe::egui::Direction::TopDown => "down",
+                eframe::egui::Direction::BottomUp => "up",
+                eframe::egui::Direction::RightToLeft => "left",
+                eframe::egui::Direction::LeftToRight => "right",
+            }.to_string()),
+            "align" if self.stretch => Some("stretch".to_string()),
+            "align" => Some(match layout.cross_align {
+                eframe::egui::Align::Min => "min",
+                eframe::egui::Align::Center => "center",
+                eframe::egui::Align::Max => "max",
+            }.to_string()),
+            "width" => Some(size_name(self.width)),
+            "height" => Some(size_name(self.height)),
+            "max-height" => Some(size_name(self.max_height)),
+            "scroll" => Some(self.scroll.name().to_string()),
+            "wrap" => Some(self.wrap.to_string()),
+            "gap" => Some(size_name(self.gap)),
+            "justify" => Some(self.justify.name().to_string()),
+            _ => None,
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
         match attr.as_str() {
             "direction" => {
                 let direction = match value.as_str() {
@@ -150,7 +342,7 @@ impl Element for Div {
                     "left" => eframe::egui::Direction::RightToLeft,
                     "right" => eframe::egui::Direction::LeftToRight,
                     _ => {
-                        executer.log_error(format!("Invalid direction '{}'", value));
+                        errors.log_error(format!("Invalid direction '{}'", value));
                         return;
                     }
                 };
@@ -164,12 +356,14 @@ impl Element for Div {
                 }
             }
             "align" => {
+                self.stretch = value == "stretch";
                 let align = match value.as_str() {
+                    "stretch" => eframe::egui::Align::Min,
                     "center" => eframe::egui::Align::Center,
                     "max" => eframe::egui::Align::Max,
                     "min" => eframe::egui::Align::Min,
                     _ => {
-                        executer.log_error(format!("Invalid align '{}'", value));
+                        errors.log_error(format!("Invalid align '{}'", value));
                         return;
                     }
                 };
@@ -182,7 +376,591 @@ impl Element for Div {
                     });
                 }
             }
-            _ => executer.log_error(format!("Unknown attribute '{}'", attr)),
+            "width" | "height" | "max-height" => {
+                let size = match parse_size(Some(value)) {
+                    Ok(size) => size,
+                    Err(why) => {
+                        errors.log_error(why);
+                        return;
+                    }
+                };
+                match attr.as_str() {
+                    "width" => self.width = size,
+                    "height" => self.height = size,
+                    _ => self.max_height = size,
+                }
+            }
+            "scroll" => match Scroll::parse(&value) {
+                Some(scroll) => self.scroll = scroll,
+                None => errors.log_error(format!("Invalid scroll '{}'", value)),
+            },
+            "wrap" => match value.parse() {
+                Ok(wrap) => self.wrap = wrap,
+                Err(_) => errors.log_error(format!("Invalid wrap '{}'", value)),
+            },
+            "gap" => match parse_size(Some(value)) {
+                Ok(gap) => self.gap = gap,
+                Err(why) => errors.log_error(why),
+            },
+            "justify" => match Justify::parse(&value) {
+                Some(justify) => self.justify = justify,
+                None => errors.log_error(format!("Invalid justify '{}'", value)),
+            },
+            _ => errors.log_error(format!("Unknown attribute '{}'", attr)),
+        }
+    }
+}
+
+pub struct Cell {
+    pub colspan: usize,
+    pub rowspan: usize,
+    pub halign: Align,
+    pub stretch: bool,
+    pub valign: Align,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { colspan: 1, rowspan: 1, halign: Align::Min, stretch: false, valign: Align::Min }
+    }
+}
+
+impl Cell {
+    pub fn get_attr(&self, attr: &str) -> Option<String> {
+        match attr {
+            "colspan" => Some(self.colspan.to_string()),
+            "rowspan" => Some(self.rowspan.to_string()),
+            "halign" if self.stretch => Some("stretch".to_string()),
+            "halign" => Some(align_name(self.halign).to_string()),
+            "valign" => Some(align_name(self.valign).to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn set_attr(&mut self, attr: &str, value: &str) -> anyhow::Result<()> {
+        match attr {
+            "colspan" | "rowspan" => {
+                let span = match value.parse() {
+                    Ok(span) if span > 0 => span,
+                    _ => return Err(anyhow!("Invalid {} '{}'", attr, value)),
+                };
+                if attr == "colspan" {
+                    self.colspan = span;
+                } else {
+                    self.rowspan = span;
+                }
+            }
+            "halign" => {
+                self.stretch = value == "stretch";
+                self.halign = if self.stretch { Align::Min } else { parse_align(value).ok_or_else(|| anyhow!("Invalid halign '{}'", value))? };
+            }
+            "valign" => self.valign = parse_align(value).ok_or_else(|| anyhow!("Invalid valign '{}'", value))?,
+            _ => return Err(anyhow!("Unknown attribute '{}'", attr)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_align(value: &str) -> Option<Align> {
+    Some(match value {
+        "min" => Align::Min,
+        "center" => Align::Center,
+        "max" => Align::Max,
+        _ => return None,
+    })
+}
+
+fn align_name(align: Align) -> &'static str {
+    match align {
+        Align::Min => "min",
+        Align::Center => "center",
+        Align::Max => "max",
+    }
+}
+
+#[derive(Default)]
+pub struct Position {
+    pub absolute: bool,
+    pub x: f32,
+    pub y: f32,
+    pub z: i32,
+}
+
+impl Position {
+    pub fn get_attr(&self, attr: &str) -> Option<String> {
+        match attr {
+            "position" => Some(if self.absolute { "absolute" } else { "static" }.to_string()),
+            "x" => Some(self.x.to_string()),
+            "y" => Some(self.y.to_string()),
+            "z" => Some(self.z.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn set_attr(&mut self, attr: &str, value: &str) -> anyhow::Result<()> {
+        match attr {
+            "position" => {
+                self.absolute = match value {
+                    "absolute" => true,
+                    "static" => false,
+                    _ => return Err(anyhow!("Invalid position '{}'", value)),
+                }
+            }
+            "x" | "y" => {
+                let offset = parse_offset(value)?;
+                if attr == "x" {
+                    self.x = offset;
+                } else {
+                    self.y = offset;
+                }
+            }
+            "z" => self.z = value.parse().map_err(|_| anyhow!("Invalid z '{}'", value))?,
+            _ => return Err(anyhow!("Unknown attribute '{}'", attr)),
+        }
+        Ok(())
+    }
+}
+
+pub struct Grid {
+    pub inner: Elements,
+    pub columns: usize,
+    pub gap: Option<f32>,
+    pub min_column_width: Option<f32>,
+    pub heights: Vec<f32>,
+}
+
+impl Grid {
+    fn place(&self, columns: usize) -> Vec<Option<(usize, usize, usize, usize)>> {
+        let mut taken: Vec<Vec<(usize, usize)>> = vec![];
+        let mut placements = vec![];
+        let (mut row, mut column) = (0, 0);
+        for node in &self.inner {
+            if node.position.absolute {
+                placements.push(None);
+                continue;
+            }
+            let colspan = node.cell.colspan.min(columns);
+            let rowspan = node.cell.rowspan.min(self.inner.len());
+            loop {
+                if column + colspan > columns {
+                    row += 1;
+                    column = 0;
+                }
+                let blocking = taken.iter().skip(row).take(rowspan).flatten().filter(|(start, end)| *start < column + colspan && column < *end).map(|(_, end)| *end).max();
+                match blocking {
+                    Some(end) => column = end,
+                    None => break,
+                }
+            }
+            if taken.len() < row + rowspan {
+                taken.resize(row + rowspan, vec![]);
+            }
+            for cells in &mut taken[row..row + rowspan] {
+                cells.push((column, column + colspan));
+            }
+            placements.push(Some((row, column, colspan, rowspan)));
+            column += colspan;
+        }
+        placements
+    }
+}
+
+impl Element for Grid {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let available = ui.available_rect_before_wrap();
+        let gap = self.gap.map_or(ui.spacing().item_spacing, Vec2::splat);
+        let mut columns = self.columns;
+        if let Some(min_width) = self.min_column_width {
+            let fitting = ((available.width() + gap.x) / (min_width + gap.x)).floor() as usize;
+            columns = columns.min(fitting).max(1);
+        }
+        let column_width = ((available.width() - gap.x * (columns - 1) as f32) / columns as f32).max(0.0);
+        let placements = self.place(columns);
+        self.heights.resize(self.inner.len(), 0.0);
+
+        let cells = || placements.iter().zip(&self.heights).filter_map(|(placement, &height)| placement.map(|placement| (placement, height)));
+        let rows = cells().map(|((row, _, _, rowspan), _)| row + rowspan).max().unwrap_or(0);
+        let mut row_heights = vec![0.0_f32; rows];
+        for ((row, _, _, rowspan), height) in cells() {
+            if rowspan == 1 {
+                row_heights[row] = row_heights[row].max(height);
+            }
+        }
+        for ((row, _, _, rowspan), height) in cells() {
+            if rowspan > 1 {
+                let spanned = row_heights[row..row + rowspan].iter().sum::<f32>() + gap.y * (rowspan - 1) as f32;
+                if height > spanned {
+                    row_heights[row + rowspan - 1] += height - spanned;
+                }
+            }
+        }
+        let row_tops: Vec<f32> = row_heights.iter().scan(0.0, |top, height| {
+            let row_top = *top;
+            *top += height + gap.y;
+            Some(row_top)
+        }).collect();
+
+        for (index, (node, &placement)) in self.inner.iter_mut().zip(&placements).enumerate() {
+            let Some((row, column, colspan, rowspan)) = placement else {
+                node.render(&mut ui.child_ui(available, Layout::top_down(Align::Min)), style, executer);
+                continue;
+            };
+            let cell_height = row_heights[row..row + rowspan].iter().sum::<f32>() + gap.y * (rowspan - 1) as f32;
+            let free = (cell_height - self.heights[index]).max(0.0);
+            let offset = match node.cell.valign {
+                Align::Min => 0.0,
+                Align::Center => free / 2.0,
+                Align::Max => free,
+            };
+            let min = available.min + Vec2::new(column as f32 * (column_width + gap.x), row_tops[row] + offset);
+            let width = column_width * colspan as f32 + gap.x * (colspan - 1) as f32;
+            let max_rect = Rect::from_min_size(min, Vec2::new(width, f32::INFINITY));
+            let mut cell_ui = ui.child_ui(max_rect, Layout::top_down(node.cell.halign).with_cross_justify(node.cell.stretch));
+            let height = node.render(&mut cell_ui, style, executer).rect.height();
+            if height != self.heights[index] {
+                self.heights[index] = height;
+                ui.ctx().request_repaint();
+            }
+        }
+        let height = (row_heights.iter().sum::<f32>() + gap.y * rows.saturating_sub(1) as f32).max(0.0);
+        ui.allocate_rect(Rect::from_min_size(available.min, Vec2::new(available.width(), height)), Sense::hover())
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        match attr {
+            "columns" => Some(self.columns.to_string()),
+            "gap" => Some(size_name(self.gap)),
+            "min-column-width" => Some(size_name(self.min_column_width)),
+            _ => None,
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        match attr.as_str() {
+            "columns" => match value.parse() {
+                Ok(columns) if columns > 0 => self.columns = columns,
+                _ => errors.log_error(format!("Invalid columns '{}'", value)),
+            },
+            "gap" | "min-column-width" => match parse_size(Some(value)) {
+                Ok(size) if attr == "gap" => self.gap = size,
+                Ok(size) => self.min_column_width = size,
+                Err(why) => errors.log_error(why),
+            },
+            _ => errors.log_error(format!("Unknown attribute '{}'", attr)),
+        }
+    }
+}
+
+const LIST_INDENT: f32 = 8.0;
+const LIST_MARKER_WIDTH: f32 = 20.0;
+const LIST_BULLETS: [&str; 3] = ["•", "◦", "▪"];
+
+pub struct List {
+    pub inner: Elements,
+    pub ordered: bool,
+    pub start: i64,
+    pub depth: usize,
+}
+
+impl List {
+    fn marker(&self, index: usize) -> String {
+        if self.ordered {
+            format!("{}.", self.start + index as i64)
+        } else {
+            LIST_BULLETS[self.depth % LIST_BULLETS.len()].to_string()
+        }
+    }
+}
+
+impl Element for List {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        ui.scope(|ui| {
+            for index in 0..self.inner.len() {
+                let marker = self.marker(index);
+                ui.horizontal_top(|ui| {
+                    ui.add_space(LIST_INDENT);
+                    ui.allocate_ui_with_layout(Vec2::new(LIST_MARKER_WIDTH, ui.spacing().interact_size.y), Layout::right_to_left(Align::Center), |ui| {
+                        ui.label(style.rich_text(&marker, ui, TextStyle::Body));
+                    });
+                    ui.vertical(|ui| {
+                        self.inner[index].render(ui, style, executer);
+                    });
+                });
+            }
+        }).response
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_list_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "start" {
+            Some(self.start.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        if attr == "start" {
+            match value.parse() {
+                Ok(start) => self.start = start,
+                Err(_) => errors.log_error(format!("Invalid start '{}'", value)),
+            }
+        } else {
+            errors.log_error(format!("Unknown attribute '{}'", attr))
+        }
+    }
+}
+
+pub struct ListItem {
+    pub inner: Elements,
+}
+
+impl Element for ListItem {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        ui.scope(|ui| {
+            for element in &mut self.inner {
+                element.render(ui, style, executer);
+            }
+        }).response
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
+        self.inner = vec![Node::new("p", Box::new(Paragraph { spans: vec![Span::plain(text)] }))];
+    }
+}
+
+pub struct Details {
+    pub title: String,
+    pub open: bool,
+    pub on_toggle: String,
+    pub inner: Elements,
+}
+
+impl Element for Details {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let response = CollapsingHeader::new(style.rich_text(&self.title, ui, TextStyle::Body)).id_source(ui.next_auto_id()).open(Some(self.open)).show(ui, |ui| {
+            for element in &mut self.inner {
+                element.render(ui, style, executer);
+            }
+        });
+        let mut header_response = response.header_response;
+        if header_response.clicked() {
+            self.open = !self.open;
+            header_response.mark_changed();
+            executer.queue_script(&self.on_toggle, "ontoggle");
+        }
+        match response.body_response {
+            Some(body_response) => header_response.union(body_response),
+            None => header_response,
+        }
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        match attr {
+            "title" => Some(self.title.clone()),
+            "open" => Some(self.open.to_string()),
+            "ontoggle" => Some(self.on_toggle.clone()),
+            _ => None,
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        match attr.as_str() {
+            "title" => self.title = value,
+            "open" => match value.parse() {
+                Ok(open) => self.open = open,
+                Err(_) => errors.log_error(format!("Invalid open '{}'", value)),
+            },
+            "ontoggle" => self.on_toggle = value,
+            _ => errors.log_error(format!("Unknown attribute '{}'", attr)),
+        }
+    }
+
+    fn change_event(&self) -> &'static str {
+        "toggle"
+    }
+}
+
+pub struct Tabs {
+    pub inner: Elements,
+    pub selected: usize,
+    pub on_change: String,
+}
+
+impl Element for Tabs {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let mut changed = false;
+        let mut response = ui.scope(|ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (index, tab) in self.inner.iter().enumerate() {
+                    let title = tab.get_attr("title").unwrap_or_else(|| format!("Tab {}", index + 1));
+                    if ui.selectable_label(index == self.selected, style.rich_text(&title, ui, TextStyle::Body)).clicked() && index != self.selected {
+                        self.selected = index;
+                        changed = true;
+                        executer.queue_script(&self.on_change, "onchange");
+                    }
+                }
+            });
+            ui.separator();
+            if let Some(tab) = self.inner.get_mut(self.selected) {
+                tab.render(ui, style, executer);
+            }
+        }).response;
+        if changed {
+            response.mark_changed();
+        }
+        response
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        match attr {
+            "selected" => Some(self.selected.to_string()),
+            "onchange" => Some(self.on_change.clone()),
+            _ => None,
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        match attr.as_str() {
+            "selected" => match value.parse() {
+                Ok(selected) => self.selected = selected,
+                Err(_) => errors.log_error(format!("Invalid selected '{}'", value)),
+            },
+            "onchange" => self.on_change = value,
+            _ => errors.log_error(format!("Unknown attribute '{}'", attr)),
+        }
+    }
+}
+
+pub struct Tab {
+    pub title: String,
+    pub inner: Elements,
+}
+
+impl Element for Tab {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        ui.scope(|ui| {
+            for element in &mut self.inner {
+                element.render(ui, style, executer);
+            }
+        }).response
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        Some(&mut self.inner)
+    }
+
+    fn set_inner(&mut self, new: Elements, _: &mut Errors) {
+        self.inner = new;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "title" {
+            Some(self.title.clone())
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        if attr == "title" {
+            self.title = value;
+        } else {
+            errors.log_error(format!("Unknown attribute '{}'", attr))
+        }
+    }
+}
+
+pub struct CodeBlock {
+    pub code: String,
+    pub lang: Option<String>,
+}
+
+impl Element for CodeBlock {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, _: &mut Executer) -> Response {
+        Frame::none().fill(ui.visuals().extreme_bg_color).rounding(4.0).inner_margin(6.0).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(lang) = &self.lang {
+                    ui.label(RichText::new(lang).size(style.font_id(ui, TextStyle::Small).size).weak());
+                }
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui.small_button("Copy").clicked() {
+                        ui.ctx().copy_text(self.code.clone());
+                    }
+                });
+            });
+            let font_id = FontId::monospace(style.font_size_or(TextStyle::Monospace.resolve(ui.style()).size));
+            let job = highlight(&self.code, self.lang.as_deref(), font_id, ui.visuals());
+            ScrollArea::horizontal().id_source(ui.next_auto_id()).show(ui, |ui| {
+                ui.add(Label::new(job).wrap(false));
+            });
+        }).response
+    }
+
+    fn get_text(&self) -> Option<String> {
+        Some(self.code.clone())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
+        self.code = text;
+    }
+
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "lang" {
+            self.lang.clone()
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        if attr == "lang" {
+            self.lang = Some(value);
+        } else {
+            errors.log_error(format!("Unknown attribute '{}'", attr))
         }
     }
 }
@@ -190,16 +968,23 @@ impl Element for Div {
 pub struct Space;
 
 impl Element for Space {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, _: &mut Executer) {
-        ui.add_space(ui.spacing().item_spacing.x);
+    fn render(&mut self, ui: &mut eframe::egui::Ui, _: &Style, _: &mut Executer) -> Response {
+        let amount = ui.spacing().item_spacing.x;
+        let size = if ui.layout().main_dir().is_horizontal() { Vec2::new(amount, 0.0) } else { Vec2::new(0.0, amount) };
+        ui.allocate_exact_size(size, Sense::hover()).1
     }
 }
 
 pub struct Divider;
 
 impl Element for Divider {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, _: &mut Executer) {
-        ui.separator();
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, _: &mut Executer) -> Response {
+        ui.scope(|ui| {
+            if let Some(color) = style.color {
+                ui.visuals_mut().widgets.noninteractive.bg_stroke.color = color;
+            }
+            ui.separator()
+        }).inner
     }
 }
 
@@ -209,19 +994,31 @@ pub struct WebLink {
 }
 
 impl Element for WebLink {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, _: &mut Executer) {
-        ui.hyperlink_to(&self.text, &self.dst);
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, _: &mut Executer) -> Response {
+        ui.hyperlink_to(style.rich_text(&self.text, ui, TextStyle::Body), &self.dst)
     }
 
-    fn set_text(&mut self, text: String, _: &mut Executer) {
+    fn get_text(&self) -> Option<String> {
+        Some(self.text.clone())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
         self.text = text;
     }
 
-    fn set_attr(&mut self, attr: String, value: String, executer: &mut Executer) {
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "dst" {
+            Some(self.dst.clone())
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
         if attr == "dst" {
             self.dst = value;
         } else {
-            executer.log_error(format!("Unknown attribute '{}'", attr))
+            errors.log_error(format!("Unknown attribute '{}'", attr))
         }
     }
 }
@@ -232,21 +1029,35 @@ pub struct Link {
 }
 
 impl Element for Link {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, executer: &mut Executer) {
-        if ui.link(&self.text).clicked() {
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let response = ui.link(style.rich_text(&self.text, ui, TextStyle::Body));
+        if response.clicked() {
             executer.send_change(crate::lua::DocumentChange::SetLocation(self.dst.clone()));
         }
+        response
     }
 
-    fn set_text(&mut self, text: String, _: &mut Executer) {
+    fn get_text(&self) -> Option<String> {
+        Some(self.text.clone())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
         self.text = text;
     }
 
-    fn set_attr(&mut self, attr: String, value: String, executer: &mut Executer) {
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "dst" {
+            Some(self.dst.clone())
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
         if attr == "dst" {
             self.dst = value;
         } else {
-            executer.log_error(format!("Unknown attribute '{}'", attr))
+            errors.log_error(format!("Unknown attribute '{}'", attr))
         }
     }
 }
@@ -257,21 +1068,80 @@ pub struct FakeLink {
 }
 
 impl Element for FakeLink {
-    fn render(&mut self, ui: &mut eframe::egui::Ui, _: Style, executer: &mut Executer) {
-        if ui.link(&self.text).clicked() {
-            executer.try_run(&self.on_click, "onclick");
+    fn render(&mut self, ui: &mut eframe::egui::Ui, style: &Style, executer: &mut Executer) -> Response {
+        let response = ui.link(style.rich_text(&self.text, ui, TextStyle::Body));
+        if response.clicked() {
+            executer.queue_script(&self.on_click, "onclick");
         }
+        response
     }
 
-    fn set_text(&mut self, text: String, _: &mut Executer) {
+    fn get_text(&self) -> Option<String> {
+        Some(self.text.clone())
+    }
+
+    fn set_text(&mut self, text: String, _: &mut Errors) {
         self.text = text;
     }
 
-    fn set_attr(&mut self, attr: String, value: String, executer: &mut Executer) {
+    fn get_attr(&self, attr: &str) -> Option<String> {
+        if attr == "onclick" {
+            Some(self.on_click.clone())
+        } else {
+            None
+        }
+    }
+
+    fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
         if attr == "onclick" {
             self.on_click = value;
         } else {
-            executer.log_error(format!("Unknown attribute '{}'", attr))
+            errors.log_error(format!("Unknown attribute '{}'", attr))
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(cells: &[(usize, usize)]) -> Grid {
+        let inner = cells.iter().map(|&(colspan, rowspan)| {
+            let mut node = Node::new("space", Box::new(Space));
+            node.cell.colspan = colspan;
+            node.cell.rowspan = rowspan;
+            node
+        }).collect();
+        Grid { inner, columns: 2, gap: None, min_column_width: None, heights: vec![] }
+    }
+
+    #[test]
+    fn places_cells_in_rows() {
+        let placements = grid(&[(1, 1), (1, 1), (1, 1)]).place(2);
+        assert_eq!(placements, [Some((0, 0, 1, 1)), Some((0, 1, 1, 1)), Some((1, 0, 1, 1))]);
+    }
+
+    #[test]
+    fn wraps_and_clamps_colspans() {
+        let placements = grid(&[(1, 1), (5, 1), (1, 1)]).place(2);
+        assert_eq!(placements, [Some((0, 0, 1, 1)), Some((1, 0, 2, 1)), Some((2, 0, 1, 1))]);
+    }
+
+    #[test]
+    fn skips_cells_taken_by_rowspans() {
+        let placements = grid(&[(1, 2), (1, 1), (1, 1), (1, 1)]).place(2);
+        assert_eq!(placements, [Some((0, 0, 1, 2)), Some((0, 1, 1, 1)), Some((1, 1, 1, 1)), Some((2, 0, 1, 1))]);
+    }
+
+    #[test]
+    fn limits_rowspans_to_the_number_of_cells() {
+        let placements = grid(&[(1, usize::MAX), (1, 1)]).place(2);
+        assert_eq!(placements, [Some((0, 0, 1, 2)), Some((0, 1, 1, 1))]);
+    }
+
+    #[test]
+    fn leaves_out_absolute_cells() {
+        let mut grid = grid(&[(1, 1), (1, 1), (1, 1)]);
+        grid.inner[1].position.absolute = true;
+        assert_eq!(grid.place(2), [Some((0, 0, 1, 1)), None, Some((0, 1, 1, 1))]);
+    }
+}
@@ -0,0 +1,193 @@
+use eframe::egui::{text::LayoutJob, Color32, FontId, TextFormat, Visuals};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Tag,
+    Attribute,
+}
+
+struct Language {
+    keywords: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const LUA: Language = Language {
+    keywords: &[
+        "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+        "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+    ],
+    line_comment: Some("--"),
+    block_comment: Some(("--[[", "]]")),
+    quotes: &['"', '\''],
+};
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+        "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+        "use", "where", "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+};
+
+const JSON: Language = Language {
+    keywords: &["true", "false", "null"],
+    line_comment: None,
+    block_comment: None,
+    quotes: &['"'],
+};
+
+fn token_color(token: Token, visuals: &Visuals) -> Color32 {
+    let dark = visuals.dark_mode;
+    match token {
+        Token::Plain => visuals.text_color(),
+        Token::Keyword => if dark { Color32::from_rgb(198, 120, 221) } else { Color32::from_rgb(166, 38, 164) },
+        Token::String => if dark { Color32::from_rgb(152, 195, 121) } else { Color32::from_rgb(80, 161, 79) },
+        Token::Number => if dark { Color32::from_rgb(209, 154, 102) } else { Color32::from_rgb(152, 104, 1) },
+        Token::Comment => visuals.weak_text_color(),
+        Token::Tag => if dark { Color32::from_rgb(224, 108, 117) } else { Color32::from_rgb(228, 86, 73) },
+        Token::Attribute => if dark { Color32::from_rgb(97, 175, 239) } else { Color32::from_rgb(64, 120, 242) },
+    }
+}
+
+pub fn highlight(code: &str, lang: Option<&str>, font_id: FontId, visuals: &Visuals) -> LayoutJob {
+    let tokens = match lang {
+        Some("lua") => tokenize(code, &LUA),
+        Some("rust" | "rs") => tokenize(code, &RUST),
+        Some("json") => tokenize(code, &JSON),
+        Some("rml" | "html" | "xml") => tokenize_rml(code),
+        _ => vec![(Token::Plain, code)],
+    };
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = f32::INFINITY;
+    for (token, text) in tokens {
+        job.append(text, 0.0, TextFormat::simple(font_id.clone(), token_color(token, visuals)));
+    }
+    job
+}
+
+fn tokenize<'a>(code: &'a str, language: &Language) -> Vec<(Token, &'a str)> {
+    let mut tokens = vec![];
+    let mut plain_start = 0;
+    let mut index = 0;
+    while index < code.len() {
+        let rest = &code[index..];
+        let chr = rest.chars().next().unwrap();
+        let token = if let Some(len) = comment_len(rest, language) {
+            Some((Token::Comment, len))
+        } else if language.quotes.contains(&chr) {
+            Some((Token::String, string_len(rest, chr)))
+        } else if chr.is_ascii_digit() && !preceded_by_word(code, index) {
+            Some((Token::Number, rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')).unwrap_or(rest.len())))
+        } else if (chr.is_alphabetic() || chr == '_') && !preceded_by_word(code, index) {
+            let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            if language.keywords.contains(&&rest[..len]) {
+                Some((Token::Keyword, len))
+            } else {
+                index += len;
+                continue;
+            }
+        } else {
+            None
+        };
+        match token {
+            Some((token, len)) => {
+                if plain_start < index {
+                    tokens.push((Token::Plain, &code[plain_start..index]));
+                }
+                tokens.push((token, &code[index..index + len]));
+                index += len;
+                plain_start = index;
+            }
+            None => index += chr.len_utf8(),
+        }
+    }
+    if plain_start < code.len() {
+        tokens.push((Token::Plain, &code[plain_start..]));
+    }
+    tokens
+}
+
+fn preceded_by_word(code: &str, index: usize) -> bool {
+    code[..index].chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn comment_len(rest: &str, language: &Language) -> Option<usize> {
+    if let Some((start, end)) = language.block_comment {
+        if let Some(body) = rest.strip_prefix(start) {
+            return Some(body.find(end).map_or(rest.len(), |pos| start.len() + pos + end.len()));
+        }
+    }
+    if let Some(start) = language.line_comment {
+        if rest.starts_with(start) {
+            return Some(rest.find('\n').unwrap_or(rest.len()));
+        }
+    }
+    None
+}
+
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (pos, chr) in rest.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if chr == '\\' {
+            escaped = true;
+        } else if chr == quote {
+            return pos + chr.len_utf8();
+        } else if chr == '\n' {
+            return pos;
+        }
+    }
+    rest.len()
+}
+
+fn tokenize_rml(code: &str) -> Vec<(Token, &str)> {
+    let mut tokens = vec![];
+    let mut rest = code;
+    while !rest.is_empty() {
+        let Some(tag_start) = rest.find('<') else {
+            tokens.push((Token::Plain, rest));
+            break;
+        };
+        if tag_start > 0 {
+            tokens.push((Token::Plain, &rest[..tag_start]));
+        }
+        rest = &rest[tag_start..];
+        let name_len = rest[1..].find([' ', '>']).map_or(rest.len(), |pos| pos + 1);
+        tokens.push((Token::Tag, &rest[..name_len]));
+        rest = &rest[name_len..];
+        while !rest.is_empty() {
+            let chr = rest.chars().next().unwrap();
+            let len = if chr == '>' {
+                tokens.push((Token::Tag, ">"));
+                rest = &rest[1..];
+                break;
+            } else if chr == '"' {
+                let len = string_len(rest, '"');
+                tokens.push((Token::String, &rest[..len]));
+                len
+            } else if chr == ' ' || chr == '=' {
+                let len = rest.find(|c: char| c != ' ' && c != '=').unwrap_or(rest.len());
+                tokens.push((Token::Plain, &rest[..len]));
+                len
+            } else {
+                let len = rest.find([' ', '=', '>', '"']).unwrap_or(rest.len());
+                tokens.push((Token::Attribute, &rest[..len]));
+                len
+            };
+            rest = &rest[len..];
+        }
+    }
+    tokens
+}
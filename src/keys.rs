@@ -0,0 +1,170 @@
+use anyhow::anyhow;
+use eframe::egui::{Key, KeyboardShortcut, Modifiers};
+use mlua::RegistryKey;
+
+pub const VIEWER_SHORTCUTS: [(KeyboardShortcut, &str); 3] = [
+    (KeyboardShortcut::new(Modifiers::NONE, Key::F5), "reload the page"),
+    (KeyboardShortcut::new(Modifiers::COMMAND, Key::R), "reload the page"),
+    (KeyboardShortcut::new(Modifiers::NONE, Key::F12), "open the console"),
+];
+
+pub struct KeyBinding {
+    pub text: String,
+    pub sequence: Vec<KeyboardShortcut>,
+    pub callback: RegistryKey,
+}
+
+pub enum KeyPress {
+    Fire(usize),
+    Pending,
+    Unbound,
+}
+
+#[derive(Default)]
+pub struct KeyBindings {
+    pub bindings: Vec<KeyBinding>,
+    pending: Vec<(Key, Modifiers)>,
+}
+
+impl KeyBindings {
+    pub fn bind(&mut self, binding: KeyBinding) -> (Option<KeyBinding>, Option<String>) {
+        if let Some(old) = self.bindings.iter_mut().find(|old| same_sequence(&old.sequence, &binding.sequence)) {
+            let warning = format!("{} was bound again, the previous function was replaced", binding.text);
+            return (Some(std::mem::replace(old, binding)), Some(warning));
+        }
+        let shadowed = self.bindings.iter().find(|old| starts_with(&old.sequence, &binding.sequence) || starts_with(&binding.sequence, &old.sequence));
+        let warning = shadowed.map(|old| format!("{} and {} start the same way, the shorter one always runs first", old.text, binding.text));
+        self.bindings.push(binding);
+        (None, warning)
+    }
+
+    pub fn unbind(&mut self, sequence: &[KeyboardShortcut]) -> Option<KeyBinding> {
+        let index = self.bindings.iter().position(|binding| same_sequence(&binding.sequence, sequence))?;
+        self.pending.clear();
+        Some(self.bindings.remove(index))
+    }
+
+    pub fn clear(&mut self) -> Vec<KeyBinding> {
+        self.pending.clear();
+        std::mem::take(&mut self.bindings)
+    }
+
+    pub fn press(&mut self, key: Key, modifiers: Modifiers) -> KeyPress {
+        self.pending.push((key, modifiers));
+        loop {
+            let matching: Vec<usize> = (0..self.bindings.len()).filter(|&index| self.pending_matches(&self.bindings[index].sequence)).collect();
+            if let Some(&index) = matching.iter().find(|&&index| self.bindings[index].sequence.len() == self.pending.len()) {
+                self.pending.clear();
+                return KeyPress::Fire(index);
+            }
+            if !matching.is_empty() {
+                return KeyPress::Pending;
+            }
+            if self.pending.len() == 1 {
+                self.pending.clear();
+                return KeyPress::Unbound;
+            }
+            self.pending = vec![(key, modifiers)];
+        }
+    }
+
+    fn pending_matches(&self, sequence: &[KeyboardShortcut]) -> bool {
+        sequence.len() >= self.pending.len() && self.pending.iter().zip(sequence).all(|((key, modifiers), chord)| *key == chord.logical_key && modifiers.matches_exact(chord.modifiers))
+    }
+}
+
+pub fn parse_sequence(text: &str) -> anyhow::Result<Vec<KeyboardShortcut>> {
+    let sequence = text.split_whitespace().map(parse_chord).collect::<anyhow::Result<Vec<_>>>()?;
+    if sequence.is_empty() {
+        return Err(anyhow!("No keys in key binding"));
+    }
+    Ok(sequence)
+}
+
+fn parse_chord(text: &str) -> anyhow::Result<KeyboardShortcut> {
+    let (modifier_text, key_text) = match text.strip_suffix("++") {
+        Some(modifier_text) => (modifier_text, "+"),
+        None => text.rsplit_once('+').unwrap_or(("", text)),
+    };
+    let mut modifiers = Modifiers::NONE;
+    for name in modifier_text.split('+').filter(|name| !name.is_empty()) {
+        modifiers = modifiers | match name.to_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CTRL,
+            "cmd" | "command" => Modifiers::COMMAND,
+            "shift" => Modifiers::SHIFT,
+            "alt" | "option" => Modifiers::ALT,
+            _ => return Err(anyhow!("Unknown modifier '{}' in '{}'", name, text)),
+        };
+    }
+    let logical_key = Key::from_name(key_text).ok_or_else(|| anyhow!("Unknown key '{}' in '{}'", key_text, text))?;
+    Ok(KeyboardShortcut::new(modifiers, logical_key))
+}
+
+pub fn viewer_conflict(sequence: &[KeyboardShortcut]) -> Option<&'static str> {
+    sequence.iter().find_map(|chord| VIEWER_SHORTCUTS.iter().find(|(shortcut, _)| same_chord(shortcut, chord))).map(|(_, action)| *action)
+}
+
+fn same_chord(a: &KeyboardShortcut, b: &KeyboardShortcut) -> bool {
+    a.logical_key == b.logical_key
+        && a.modifiers.alt == b.modifiers.alt
+        && a.modifiers.shift == b.modifiers.shift
+        && (a.modifiers.ctrl || a.modifiers.command) == (b.modifiers.ctrl || b.modifiers.command)
+}
+
+fn same_sequence(a: &[KeyboardShortcut], b: &[KeyboardShortcut]) -> bool {
+    a.len() == b.len() && starts_with(a, b)
+}
+
+fn starts_with(sequence: &[KeyboardShortcut], prefix: &[KeyboardShortcut]) -> bool {
+    sequence.len() >= prefix.len() && sequence.iter().zip(prefix).all(|(a, b)| same_chord(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+
+    fn binding(lua: &Lua, text: &str, callback: i32) -> KeyBinding {
+        KeyBinding { text: text.to_string(), sequence: parse_sequence(text).unwrap(), callback: lua.create_registry_value(callback).unwrap() }
+    }
+
+    #[test]
+    fn rebinding_returns_the_replaced_binding() {
+        let lua = Lua::new();
+        let mut bindings = KeyBindings::default();
+        let (replaced, warning) = bindings.bind(binding(&lua, "ctrl+k", 1));
+        assert!(replaced.is_none() && warning.is_none());
+        let (replaced, warning) = bindings.bind(binding(&lua, "Ctrl+K", 2));
+        assert_eq!(lua.registry_value::<i32>(&replaced.unwrap().callback).unwrap(), 1);
+        assert!(warning.is_some());
+        assert_eq!(bindings.bindings.len(), 1);
+        assert_eq!(lua.registry_value::<i32>(&bindings.bindings[0].callback).unwrap(), 2);
+    }
+
+    #[test]
+    fn warns_about_shadowed_sequences() {
+        let lua = Lua::new();
+        let mut bindings = KeyBindings::default();
+        bindings.bind(binding(&lua, "g", 1));
+        let (replaced, warning) = bindings.bind(binding(&lua, "g g", 2));
+        assert!(replaced.is_none() && warning.is_some());
+    }
+
+    #[test]
+    fn finds_viewer_shortcuts_in_any_chord() {
+        assert_eq!(viewer_conflict(&parse_sequence("F5").unwrap()), Some("reload the page"));
+        assert_eq!(viewer_conflict(&parse_sequence("g F12").unwrap()), Some("open the console"));
+        assert_eq!(viewer_conflict(&parse_sequence("g ctrl+r").unwrap()), Some("reload the page"));
+        assert_eq!(viewer_conflict(&parse_sequence("g shift+F5 r").unwrap()), None);
+    }
+
+    #[test]
+    fn fires_sequences() {
+        let lua = Lua::new();
+        let mut bindings = KeyBindings::default();
+        bindings.bind(binding(&lua, "g g", 1));
+        assert!(matches!(bindings.press(Key::G, Modifiers::NONE), KeyPress::Pending));
+        assert!(matches!(bindings.press(Key::G, Modifiers::NONE), KeyPress::Fire(0)));
+        assert!(matches!(bindings.press(Key::H, Modifiers::NONE), KeyPress::Unbound));
+    }
+}
@@ -1,72 +1,421 @@
-use std::{collections::VecDeque, sync::mpsc::{self, Receiver, SyncSender}};
-use eframe::egui::{Context, OpenUrl};
-use mlua::{Error, FromLua, Lua, Result, Table, UserData, Value};
-use crate::parser::{parse_string, Page};
+use std::{cell::{Cell, RefCell}, collections::VecDeque, fs, path::{Path, PathBuf}, rc::Rc, sync::mpsc::{self, Receiver, Sender}, time::{Duration, Instant}};
+use eframe::egui::{Context, KeyboardShortcut, Modifiers, OpenUrl, Vec2};
+use mlua::{Error, FromLua, Function, IntoLua, IntoLuaMulti, Lua, MultiValue, RegistryKey, Result, Table, ThreadStatus, UserData, Value};
+use crate::{console::ConsoleEntry, dialogs::{show_dialog, show_toasts, show_unresponsive, Dialog, DialogAnswer, DialogKind, Toast, ToastKind}, keys::{parse_sequence, viewer_conflict, KeyBinding, KeyBindings, KeyPress}, parser::{Errors, Page}, scripts::{set_require, ChunkCache}, sandbox::{resolve_path, sandboxed_lua, Deadline, Limits, Permission}};
 
 pub struct Executer {
     pub lua: Lua,
-    pub console: Vec<String>,
+    pub console: Vec<ConsoleEntry>,
     changes: Receiver<DocumentChange>,
-    changes_sender: SyncSender<DocumentChange>,
+    changes_sender: Sender<DocumentChange>,
+    queued: Vec<Queued>,
+    dialogs: VecDeque<Dialog>,
+    toasts: Vec<Toast>,
+    timers: Vec<Timer>,
+    timer_waiters: Vec<(u64, RegistryKey)>,
+    handler_stack: Vec<Rc<Vec<Handler>>>,
+    origin_stack: Vec<Origin>,
+    claimed_events: Vec<&'static str>,
+    page_listeners: Vec<(String, RegistryKey)>,
+    loaded: bool,
+    page_size: Option<Vec2>,
+    visibility: Option<(bool, bool)>,
+    key_bindings: KeyBindings,
+    pub limits: Limits,
+    extra_time: u32,
+    deadline: Deadline,
+    scripts_stopped: bool,
+    chunks: ChunkCache,
+    dir: PathBuf,
+    source: Rc<str>,
 }
 
 impl Executer {
     pub fn log(&mut self, msg: impl ToString) {
-        self.console.push(msg.to_string());
+        self.console.push(ConsoleEntry::message(msg.to_string()));
     }
 
     pub fn log_error(&mut self, msg: impl ToString) {
-        self.console.push("Error: ".to_string() + &msg.to_string());
+        self.console.push(ConsoleEntry::error(msg.to_string(), &self.dir, &self.source));
     }
 
-    pub fn try_run(&mut self, code: &str, name: &str) {
-        if let Err(why) = self.lua.load(code).set_name(name).exec() {
+    fn scripts_paused(&self) -> bool {
+        self.scripts_stopped || self.deadline.timed_out()
+    }
+
+    fn time_limit(&self) -> u64 {
+        self.limits.time_ms * self.extra_time as u64
+    }
+
+    fn begin_run(&self) -> bool {
+        if self.scripts_paused() {
+            return false;
+        }
+        self.deadline.set(Some(Instant::now() + Duration::from_millis(self.time_limit())));
+        self.deadline.watch(&self.lua);
+        let _ = self.lua.set_memory_limit(self.limits.memory_mb * 1024 * 1024);
+        true
+    }
+
+    fn stop_scripts(&mut self) {
+        self.scripts_stopped = true;
+        self.queued.clear();
+        self.dialogs.clear();
+        self.timers.clear();
+        self.timer_waiters.clear();
+        self.page_listeners.clear();
+        self.key_bindings.clear();
+        self.log("Scripts on this page were stopped");
+    }
+
+    pub fn try_run(&mut self, code: &str, name: &str, line: usize) {
+        if !self.begin_run() {
+            return;
+        }
+        let result = self.lua.load(pad_lines(code, line)).set_name(format!("={}", name)).into_function().and_then(|function| start(&self.lua, &self.deadline, Value::Function(function), ()));
+        self.suspend(result);
+    }
+
+    pub fn run_file(&mut self, dir: &Path, src: &str) {
+        if !self.begin_run() {
+            return;
+        }
+        let result = self.chunks.load(&self.lua, dir, src).and_then(|function| start(&self.lua, &self.deadline, Value::Function(function), ()));
+        self.suspend(result);
+    }
+
+    fn run_callback<A: for<'lua> IntoLuaMulti<'lua>>(&mut self, callback: &RegistryKey, args: A) {
+        let result = self.lua.registry_value::<Value>(callback).and_then(|callback| start(&self.lua, &self.deadline, callback, args));
+        self.suspend(result);
+    }
+
+    fn suspend(&mut self, result: Result<Option<Suspended>>) {
+        match result {
+            Ok(None) => {}
+            Ok(Some(Suspended { thread, wait: Wait::Sleep(duration) })) => match due_in(duration) {
+                Ok(due) => self.timers.push(Timer { id: None, callback: thread, due, interval: None }),
+                Err(why) => self.log_error(why),
+            },
+            Ok(Some(Suspended { thread, wait: Wait::Timer(id) })) => {
+                self.send_change(DocumentChange::WaitTimer(id, thread));
+            }
+            Ok(Some(Suspended { thread, wait: Wait::Dialog(message, kind) })) => {
+                self.send_change(DocumentChange::ShowDialog(Dialog { message, kind, callback: Some(thread) }));
+            }
+            Err(why) => self.log_error(why),
+        }
+    }
+
+    pub fn queue_script(&mut self, code: &str, event: &str) {
+        let origin = self.origin_stack.last().cloned().unwrap_or_default();
+        self.queued.push(Queued::Script(code.to_string(), origin.chunk_name(event), origin.line));
+    }
+
+    pub fn push_handlers(&mut self, origin: &Origin, handlers: Rc<Vec<Handler>>) {
+        self.handler_stack.push(handlers);
+        self.origin_stack.push(origin.clone());
+    }
+
+    pub fn pop_handlers(&mut self) {
+        self.handler_stack.pop();
+        self.origin_stack.pop();
+    }
+
+    pub fn reset_events(&mut self) {
+        self.claimed_events.clear();
+    }
+
+    pub fn claim_event(&mut self, kind: &'static str) -> bool {
+        if self.claimed_events.contains(&kind) {
+            return false;
+        }
+        self.claimed_events.push(kind);
+        true
+    }
+
+    pub fn fire(&mut self, event: Event) {
+        let levels = if event.bubbles { self.handler_stack.len() } else { 1 };
+        let chain: Vec<Rc<Vec<Handler>>> = self.handler_stack.iter().rev().take(levels).cloned().collect();
+        if chain.iter().any(|handlers| handlers.iter().any(|handler| handler.event == event.kind)) {
+            self.queued.push(Queued::Event(event, chain));
+        }
+    }
+
+    fn dispatch(&mut self, event: Event, chain: Vec<Rc<Vec<Handler>>>) {
+        if !self.begin_run() {
+            return;
+        }
+        let results = run_handlers(&self.lua, &self.deadline, &event, chain).unwrap_or_else(|why| vec![Err(why)]);
+        for result in results {
+            self.suspend(result);
+        }
+    }
+
+    pub fn fire_page_event(&mut self, event: PageEvent) -> bool {
+        let kind = event.kind();
+        let listeners: Vec<&RegistryKey> = self.page_listeners.iter().filter(|(event, _)| event == kind).map(|(_, listener)| listener).collect();
+        if listeners.is_empty() || !self.begin_run() {
+            return false;
+        }
+        let (prevented, errors) = match run_page_listeners(&self.lua, &event, listeners) {
+            Ok(result) => result,
+            Err(why) => (false, vec![why]),
+        };
+        for why in errors {
             self.log_error(why);
         }
+        prevented
+    }
+
+    pub fn update_page_events(&mut self, ctx: &Context, size: Vec2) {
+        if !self.loaded {
+            self.loaded = true;
+            self.fire_page_event(PageEvent::Load);
+        }
+        if self.page_size.is_some_and(|page_size| page_size != size) {
+            self.fire_page_event(PageEvent::Resize(size));
+        }
+        self.page_size = Some(size);
+        let visibility = ctx.input(|input| (!input.viewport().minimized.unwrap_or(false), input.viewport().focused.unwrap_or(true)));
+        if self.visibility.is_some_and(|old| old != visibility) {
+            self.fire_page_event(PageEvent::VisibilityChange { visible: visibility.0, focused: visibility.1 });
+        }
+        self.visibility = Some(visibility);
+    }
+
+    pub fn run_queued(&mut self) {
+        if self.scripts_stopped {
+            self.queued.clear();
+            return;
+        }
+        for queued in std::mem::take(&mut self.queued) {
+            if self.scripts_paused() {
+                self.queued.push(queued);
+                continue;
+            }
+            match queued {
+                Queued::Script(code, name, line) => self.try_run(&code, &name, line),
+                Queued::Event(event, chain) => self.dispatch(event, chain),
+                Queued::Resume(thread, value) => {
+                    if self.begin_run() {
+                        self.run_callback(&thread, value);
+                    }
+                    let _ = self.lua.remove_registry_value(thread);
+                }
+            }
+        }
+    }
+
+    pub fn run_timers(&mut self, ctx: &Context) {
+        if self.scripts_paused() {
+            return;
+        }
+        let now = Instant::now();
+        let (due, waiting): (Vec<Timer>, Vec<Timer>) = std::mem::take(&mut self.timers).into_iter().partition(|timer| timer.due <= now);
+        self.timers = waiting;
+        if !due.is_empty() {
+            ctx.request_repaint();
+        }
+        for mut timer in due {
+            if !self.begin_run() {
+                self.timers.push(timer);
+                continue;
+            }
+            self.run_callback(&timer.callback, ());
+            if let Some(id) = timer.id {
+                let (waiters, others) = std::mem::take(&mut self.timer_waiters).into_iter().partition(|(waited, _)| *waited == id);
+                self.timer_waiters = others;
+                self.queued.extend(waiters.into_iter().map(|(_, thread)| Queued::Resume(thread, true)));
+            }
+            match timer.interval {
+                Some(interval) => {
+                    timer.due = if timer.due + interval > now { timer.due + interval } else { now + interval };
+                    self.timers.push(timer);
+                }
+                None => {
+                    let _ = self.lua.remove_registry_value(timer.callback);
+                }
+            }
+        }
+    }
+
+    pub fn request_timer_repaint(&self, ctx: &Context) {
+        if let Some(due) = self.timers.iter().map(|timer| timer.due).min() {
+            ctx.request_repaint_after(due.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    pub fn run_key_bindings(&mut self, ctx: &Context) {
+        if !self.dialogs.is_empty() || self.key_bindings.bindings.is_empty() || self.scripts_paused() {
+            return;
+        }
+        let events = ctx.input(|input| input.events.clone());
+        let mut consumed = vec![];
+        let mut fired = vec![];
+        for (index, event) in events.iter().enumerate() {
+            let eframe::egui::Event::Key { key, pressed: true, repeat: false, modifiers, .. } = event else {
+                continue;
+            };
+            match self.key_bindings.press(*key, *modifiers) {
+                KeyPress::Fire(binding) => {
+                    consumed.push(index);
+                    fired.push(binding);
+                }
+                KeyPress::Pending => consumed.push(index),
+                KeyPress::Unbound => {}
+            }
+        }
+        if !consumed.is_empty() {
+            ctx.input_mut(|input| {
+                let mut index = 0;
+                input.events.retain(|_| {
+                    index += 1;
+                    !consumed.contains(&(index - 1))
+                });
+            });
+        }
+        for binding in fired {
+            if !self.begin_run() {
+                break;
+            }
+            let binding = &self.key_bindings.bindings[binding];
+            let result = self.lua.registry_value::<Value>(&binding.callback).and_then(|callback| start(&self.lua, &self.deadline, callback, binding.text.clone()));
+            self.suspend(result);
+        }
+    }
+
+    pub fn render_dialogs(&mut self, ctx: &Context) {
+        show_toasts(ctx, &mut self.toasts);
+        if self.deadline.timed_out() && !self.scripts_stopped {
+            match show_unresponsive(ctx, self.time_limit()) {
+                Some(true) => self.stop_scripts(),
+                Some(false) => self.extra_time = self.extra_time.saturating_mul(2),
+                None => return,
+            }
+            self.deadline.clear_timed_out();
+            return;
+        }
+        let Some(dialog) = self.dialogs.front_mut() else {
+            return;
+        };
+        if let Some(answer) = show_dialog(ctx, dialog) {
+            let dialog = self.dialogs.pop_front().unwrap();
+            self.answer_dialog(dialog, answer);
+        }
+    }
+
+    fn answer_dialog(&mut self, dialog: Dialog, answer: DialogAnswer) {
+        let Some(key) = dialog.callback else {
+            return;
+        };
+        if !self.begin_run() {
+            return;
+        }
+        match answer {
+            DialogAnswer::Ok => self.run_callback(&key, ()),
+            DialogAnswer::Confirm(confirmed) => self.run_callback(&key, confirmed),
+            DialogAnswer::Prompt(text) => self.run_callback(&key, text),
+        }
+        let _ = self.lua.remove_registry_value(key);
     }
 
     pub fn update_document(&mut self, page: &mut Page, location: &mut Option<String>, title: &mut Option<String>, ctx: &Context) {
         while let Ok(change) = self.changes.try_recv() {
             match change {
                 DocumentChange::Log(text) => self.log(text),
-                DocumentChange::SetInner(path, inner) => {
-                    let elements = match parse_string(&inner) {
-                        Ok(els) => els,
-                        Err(why) => {
-                            self.log_error(why);
-                            continue;
-                        }
-                    };
-                    page.set_path_inner(path, elements, self);
-                }
-                DocumentChange::SetText(path, text) => {
-                    page.set_path_text(path, text, self);
-                }
+                DocumentChange::LogError(text) => self.log_error(text),
                 DocumentChange::SetLocation(link) => {
                     *location = Some(link);
                 }
                 DocumentChange::OpenLink(link) => {
                     ctx.open_url(OpenUrl::same_tab(link));
                 }
-                DocumentChange::SetAttr(path, attr, value) => {
-                    page.set_path_attr(path, attr, value, self);
-                }
                 DocumentChange::SetTitle(value) => {
                     *title = Some(value);
                 }
+                DocumentChange::ShowDialog(dialog) => {
+                    self.dialogs.push_back(dialog);
+                }
+                DocumentChange::ShowToast(toast) => {
+                    self.toasts.push(toast);
+                }
+                DocumentChange::AddListener(path, event, listener) => {
+                    page.add_path_listener(path, event, listener, self);
+                }
+                DocumentChange::AddPageListener(event, listener) => {
+                    self.page_listeners.push((event, listener));
+                }
+                DocumentChange::SetTimer(timer) => {
+                    self.timers.push(timer);
+                }
+                DocumentChange::BindKey(binding) => {
+                    let (replaced, warning) = self.key_bindings.bind(binding);
+                    if let Some(replaced) = replaced {
+                        let _ = self.lua.remove_registry_value(replaced.callback);
+                    }
+                    if let Some(warning) = warning {
+                        self.log(warning);
+                    }
+                }
+                DocumentChange::UnbindKey(sequence) => {
+                    if let Some(binding) = self.key_bindings.unbind(&sequence) {
+                        let _ = self.lua.remove_registry_value(binding.callback);
+                    }
+                }
+                DocumentChange::ClearTimer(id) => {
+                    if let Some(index) = self.timers.iter().position(|timer| timer.id == Some(id)) {
+                        let timer = self.timers.remove(index);
+                        let _ = self.lua.remove_registry_value(timer.callback);
+                    }
+                    let (waiters, others) = std::mem::take(&mut self.timer_waiters).into_iter().partition(|(waited, _)| *waited == id);
+                    self.timer_waiters = others;
+                    self.queued.extend(waiters.into_iter().map(|(_, thread)| Queued::Resume(thread, false)));
+                }
+                DocumentChange::WaitTimer(id, thread) => {
+                    if self.timers.iter().any(|timer| timer.id == Some(id)) {
+                        self.timer_waiters.push((id, thread));
+                    } else {
+                        self.queued.push(Queued::Resume(thread, false));
+                    }
+                }
             }
         }
     }
 
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::sync_channel(255);
-        Self { lua: Lua::new(), console: vec![], changes: rx, changes_sender: tx }
+        let (tx, rx) = mpsc::channel();
+        Self { lua: Lua::new(), console: vec![], changes: rx, changes_sender: tx, queued: vec![], dialogs: VecDeque::new(), toasts: vec![], timers: vec![], timer_waiters: vec![], handler_stack: vec![], origin_stack: vec![], claimed_events: vec![], page_listeners: vec![], loaded: false, page_size: None, visibility: None, key_bindings: KeyBindings::default(), limits: Limits::default(), extra_time: 1, deadline: Deadline::default(), scripts_stopped: false, chunks: ChunkCache::default(), dir: PathBuf::new(), source: "".into() }
+    }
+
+    pub fn stop(&mut self) {
+        self.lua = sandboxed_lua().unwrap();
+        self.extra_time = 1;
+        self.deadline.set(None);
+        self.deadline.clear_timed_out();
+        self.scripts_stopped = false;
+        self.deadline.watch_coroutines(&self.lua).unwrap();
+        self.queued.clear();
+        self.dialogs.clear();
+        self.toasts.clear();
+        self.timers.clear();
+        self.timer_waiters.clear();
+        self.page_listeners.clear();
+        self.loaded = false;
+        self.page_size = None;
+        self.visibility = None;
+        self.key_bindings.clear();
+        while self.changes.try_recv().is_ok() {}
     }
 
-    pub fn init_lua(&mut self) {
-        self.lua = Lua::new();
+    pub fn init_lua(&mut self, page: Rc<RefCell<Page>>, permissions: Vec<Permission>) {
+        self.stop();
         let sender = self.changes_sender.clone();
-        let document = Document { changes_sender: sender };
+        let dir = page.borrow().dir.clone();
+        self.dir = dir.clone();
+        self.source = page.borrow().source.clone();
+        set_require(&self.lua, dir.clone(), self.chunks.clone()).unwrap();
+        let document = Document { changes_sender: sender, page, next_timer_id: Rc::new(Cell::new(1)), permissions, dir };
+        let document = load_document(&self.lua, document).unwrap();
         self.lua.globals().set("document", document).unwrap();
     }
 
@@ -77,7 +426,361 @@ impl Executer {
 
 #[derive(Clone)]
 pub struct Document {
-    pub changes_sender: SyncSender<DocumentChange>,
+    pub changes_sender: Sender<DocumentChange>,
+    pub page: Rc<RefCell<Page>>,
+    pub next_timer_id: Rc<Cell<u64>>,
+    pub permissions: Vec<Permission>,
+    pub dir: PathBuf,
+}
+
+impl Document {
+    fn file_path(&self, path: &str, permission: Permission) -> Result<PathBuf> {
+        if !self.permissions.contains(&permission) {
+            return Err(Error::external(format!("The page does not have the {} permission", permission.name())));
+        }
+        resolve_path(&self.dir, path).map_err(Error::external)
+    }
+
+    fn edit_page(&self, edit: impl FnOnce(&mut Page, &mut Errors)) -> Result<()> {
+        let mut page = self.page.try_borrow_mut().map_err(|_| Error::external("Document is busy"))?;
+        let mut errors = Errors::default();
+        edit(&mut page, &mut errors);
+        for error in errors.0 {
+            if self.changes_sender.send(DocumentChange::LogError(error)).is_err() {
+                return Err(Error::external("Could not send document change"));
+            }
+        }
+        Ok(())
+    }
+
+    fn add_timer(&self, lua: &Lua, callback: Function, ms: f64, repeat: bool) -> Result<u64> {
+        let delay = timer_delay(ms, repeat)?;
+        let id = self.next_timer_id.get();
+        self.next_timer_id.set(id + 1);
+        let timer = Timer { id: Some(id), callback: lua.create_registry_value(callback)?, due: due_in(delay)?, interval: repeat.then_some(delay) };
+        match self.changes_sender.send(DocumentChange::SetTimer(timer)) {
+            Ok(_) => Ok(id),
+            Err(_) => Err(Error::external("Could not send document change")),
+        }
+    }
+}
+
+const SCRIPT_THREADS: &str = "script_threads";
+
+fn load_document<'lua>(lua: &'lua Lua, raw: impl IntoLua<'lua>) -> Result<Table<'lua>> {
+    let script_threads = lua.create_table()?;
+    script_threads.set_metatable(Some(lua.create_table_from([("__mode", "k")])?));
+    lua.set_named_registry_value(SCRIPT_THREADS, script_threads.clone())?;
+    lua.load(DOCUMENT_PRELUDE).set_name("document").call((raw, script_threads))
+}
+
+const DOCUMENT_PRELUDE: &str = r#"
+    local raw, script_threads = ...
+    local yield, isyieldable, running = coroutine.yield, coroutine.isyieldable, coroutine.running
+    local function can_wait()
+        return isyieldable() and script_threads[running()] == true
+    end
+    local document = setmetatable({}, {
+        __index = function(document, name)
+            local method = raw[name]
+            if type(method) ~= "function" then
+                return method
+            end
+            local wrapper = function(_, ...) return method(raw, ...) end
+            rawset(document, name, wrapper)
+            return wrapper
+        end,
+    })
+    local function wait(name, ...)
+        if not can_wait() then
+            error("document:" .. name .. " can only wait inside a script, handler or callback and not in a coroutine made by the page", 3)
+        end
+        return yield(name, ...)
+    end
+    function document:sleep(ms)
+        return wait("sleep", ms)
+    end
+    function document:wait_timer(id)
+        return wait("timer", id)
+    end
+    function document:alert(message, callback)
+        if callback == nil and can_wait() then
+            return yield("alert", message)
+        end
+        return raw:alert(message, callback)
+    end
+    function document:confirm(message, callback)
+        if callback == nil then
+            return wait("confirm", message)
+        end
+        return raw:confirm(message, callback)
+    end
+    function document:prompt(message, default, callback)
+        if callback == nil then
+            return wait("prompt", message, default)
+        end
+        return raw:prompt(message, default, callback)
+    end
+    return document
+"#;
+
+enum Queued {
+    Script(String, String, usize),
+    Event(Event, Vec<Rc<Vec<Handler>>>),
+    Resume(RegistryKey, bool),
+}
+
+#[derive(Clone)]
+pub enum Action {
+    Script(String, Origin),
+    Function(Rc<RegistryKey>),
+}
+
+#[derive(Clone, Default)]
+pub struct Origin {
+    pub source: Rc<str>,
+    pub line: usize,
+    pub selector: Rc<str>,
+}
+
+impl Origin {
+    pub fn chunk_name(&self, what: &str) -> String {
+        match (self.source.is_empty(), self.selector.is_empty()) {
+            (true, true) => what.to_string(),
+            (true, false) => format!("{} {}", self.selector, what),
+            (false, _) => format!("{}:{} {} {}", self.source, self.line, self.selector, what),
+        }
+    }
+}
+
+fn pad_lines(code: &str, line: usize) -> String {
+    "\n".repeat(line.saturating_sub(1)) + code
+}
+
+#[derive(Clone)]
+pub struct Handler {
+    pub event: String,
+    pub action: Action,
+}
+
+pub struct Event {
+    pub kind: &'static str,
+    pub pos: Option<Vec2>,
+    pub modifiers: Modifiers,
+    pub key: Option<&'static str>,
+    pub bubbles: bool,
+}
+
+pub const EVENTS: [&str; 10] = ["click", "doubleclick", "contextmenu", "hover", "leave", "focus", "blur", "keydown", "change", "toggle"];
+pub const SCRIPT_EVENTS: [&str; 7] = ["hover", "leave", "doubleclick", "contextmenu", "focus", "blur", "keydown"];
+
+pub enum PageEvent {
+    Load,
+    Unload(Option<String>),
+    Resize(Vec2),
+    VisibilityChange { visible: bool, focused: bool },
+}
+
+impl PageEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Load => "load",
+            Self::Unload(_) => "unload",
+            Self::Resize(_) => "resize",
+            Self::VisibilityChange { .. } => "visibilitychange",
+        }
+    }
+}
+
+const PAGE_EVENTS: [&str; 4] = ["load", "unload", "resize", "visibilitychange"];
+
+fn run_page_listeners(lua: &Lua, event: &PageEvent, listeners: Vec<&RegistryKey>) -> Result<(bool, Vec<Error>)> {
+    let table = lua.create_table()?;
+    table.set("type", event.kind())?;
+    match event {
+        PageEvent::Load => {}
+        PageEvent::Unload(location) => table.set("location", location.clone())?,
+        PageEvent::Resize(size) => {
+            table.set("width", size.x)?;
+            table.set("height", size.y)?;
+        }
+        PageEvent::VisibilityChange { visible, focused } => {
+            table.set("visible", *visible)?;
+            table.set("focused", *focused)?;
+        }
+    }
+    table.set("prevent_default", lua.create_function(|_, event: Table| event.set("default_prevented", true))?)?;
+    let mut prevented = false;
+    let mut errors = vec![];
+    for listener in listeners {
+        match lua.registry_value::<Function>(listener).and_then(|listener| listener.call::<_, Option<bool>>(table.clone())) {
+            Ok(result) => prevented |= result == Some(false),
+            Err(why) => errors.push(why),
+        }
+    }
+    prevented |= table.get::<_, Option<bool>>("default_prevented")? == Some(true);
+    Ok((prevented, errors))
+}
+
+fn run_handlers(lua: &Lua, deadline: &Deadline, event: &Event, chain: Vec<Rc<Vec<Handler>>>) -> Result<Vec<Result<Option<Suspended>>>> {
+    let mut results = vec![];
+    let table = event_table(lua, event)?;
+    for handlers in chain {
+        for handler in handlers.iter().filter(|handler| handler.event == event.kind) {
+            results.push(match &handler.action {
+                Action::Script(code, origin) => lua.globals().set("event", table.clone())
+                    .and_then(|_| script_environment(lua, &table))
+                    .and_then(|environment| lua.load(pad_lines(code, origin.line)).set_name(format!("={}", origin.chunk_name(&format!("on{}", event.kind)))).set_environment(environment).into_function())
+                    .and_then(|function| start(lua, deadline, Value::Function(function), ())),
+                Action::Function(listener) => lua.registry_value::<Value>(listener)
+                    .and_then(|listener| start(lua, deadline, listener, table.clone())),
+            });
+        }
+        if table.get::<_, Option<bool>>("propagation_stopped")? == Some(true) {
+            break;
+        }
+    }
+    lua.globals().set("event", Value::Nil)?;
+    Ok(results)
+}
+
+fn script_environment<'lua>(lua: &'lua Lua, event: &Table<'lua>) -> Result<Table<'lua>> {
+    let environment = lua.create_table()?;
+    environment.set("event", event.clone())?;
+    let metatable = lua.create_table()?;
+    metatable.set("__index", lua.globals())?;
+    metatable.set("__newindex", lua.globals())?;
+    environment.set_metatable(Some(metatable));
+    Ok(environment)
+}
+
+pub struct Suspended {
+    thread: RegistryKey,
+    wait: Wait,
+}
+
+enum Wait {
+    Sleep(Duration),
+    Timer(u64),
+    Dialog(String, DialogKind),
+}
+
+fn start<'lua>(lua: &'lua Lua, deadline: &Deadline, callback: Value<'lua>, args: impl IntoLuaMulti<'lua>) -> Result<Option<Suspended>> {
+    let thread = match callback {
+        Value::Function(function) => {
+            let thread = lua.create_thread(function)?;
+            if let Some(script_threads) = lua.named_registry_value::<Option<Table>>(SCRIPT_THREADS)? {
+                script_threads.raw_set(thread.clone(), true)?;
+            }
+            thread
+        }
+        Value::Thread(thread) => thread,
+        _ => return Err(Error::external("Callback is not a function")),
+    };
+    deadline.watch_thread(&thread);
+    let result = thread.resume::<_, MultiValue>(args);
+    deadline.watch(lua);
+    let values: Vec<Value> = result?.into_iter().collect();
+    if thread.status() != ThreadStatus::Resumable {
+        return Ok(None);
+    }
+    let value = |index: usize| values.get(index).cloned().unwrap_or(Value::Nil);
+    let wait = match Option::<String>::from_lua(value(0), lua)?.as_deref() {
+        None => Wait::Sleep(Duration::ZERO),
+        Some("sleep") => Wait::Sleep(delay_from_ms(f64::from_lua(value(1), lua)?)?),
+        Some("timer") => Wait::Timer(u64::from_lua(value(1), lua)?),
+        Some("alert") => Wait::Dialog(String::from_lua(value(1), lua)?, DialogKind::Alert),
+        Some("confirm") => Wait::Dialog(String::from_lua(value(1), lua)?, DialogKind::Confirm),
+        Some("prompt") => Wait::Dialog(String::from_lua(value(1), lua)?, DialogKind::Prompt(Option::<String>::from_lua(value(2), lua)?.unwrap_or_default())),
+        Some(kind) => return Err(Error::external(format!("Can not wait for '{}'", kind))),
+    };
+    Ok(Some(Suspended { thread: lua.create_registry_value(thread)?, wait }))
+}
+
+fn event_table<'lua>(lua: &'lua Lua, event: &Event) -> Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("type", event.kind)?;
+    if let Some(pos) = event.pos {
+        table.set("x", pos.x)?;
+        table.set("y", pos.y)?;
+    }
+    if let Some(key) = event.key {
+        table.set("key", key)?;
+    }
+    let modifiers = lua.create_table()?;
+    modifiers.set("shift", event.modifiers.shift)?;
+    modifiers.set("ctrl", event.modifiers.ctrl)?;
+    modifiers.set("alt", event.modifiers.alt)?;
+    modifiers.set("command", event.modifiers.command)?;
+    table.set("modifiers", modifiers)?;
+    table.set("stop_propagation", lua.create_function(|_, event: Table| event.set("propagation_stopped", true))?)?;
+    Ok(table)
+}
+
+fn add_listener(lua: &Lua, document: &Document, path: VecDeque<usize>, event: String, listener: Function) -> Result<()> {
+    if !EVENTS.contains(&event.as_str()) {
+        return Err(Error::external(format!("Unknown event '{}'", event)));
+    }
+    let listener = lua.create_registry_value(listener)?;
+    match document.changes_sender.send(DocumentChange::AddListener(path, event, listener)) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::external("Could not send document change")),
+    }
+}
+
+pub struct ElementHandle {
+    document: Document,
+    path: VecDeque<usize>,
+}
+
+impl UserData for ElementHandle {
+    fn add_methods<'lua, M: mlua::prelude::LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("on", |lua, this, (event, listener): (String, Function)| {
+            add_listener(lua, &this.document, this.path.clone(), event, listener)
+        });
+        methods.add_method("get_text", |_, this, ()| {
+            let mut page = this.document.page.try_borrow_mut().map_err(|_| Error::external("Document is busy"))?;
+            page.get_path_text(this.path.clone()).map_err(Error::external)
+        });
+        methods.add_method("get_attr", |_, this, attr: String| {
+            let mut page = this.document.page.try_borrow_mut().map_err(|_| Error::external("Document is busy"))?;
+            page.get_path_attr(this.path.clone(), &attr).map_err(Error::external)
+        });
+        methods.add_method("set_text", |_, this, text: String| {
+            this.document.edit_page(|page, errors| page.set_path_text(this.path.clone(), text, errors))
+        });
+        methods.add_method("set_attr", |_, this, (attr, value): (String, String)| {
+            this.document.edit_page(|page, errors| page.set_path_attr(this.path.clone(), attr, value, errors))
+        });
+        methods.add_method("set_inner", |_, this, rml: String| {
+            this.document.edit_page(|page, errors| page.set_path_rml(this.path.clone(), &rml, errors))
+        });
+    }
+}
+
+pub struct Timer {
+    id: Option<u64>,
+    callback: RegistryKey,
+    due: Instant,
+    interval: Option<Duration>,
+}
+
+const MAX_DELAY_MS: f64 = 2147483647.0;
+
+fn delay_from_ms(ms: f64) -> Result<Duration> {
+    if !ms.is_finite() || ms > MAX_DELAY_MS {
+        return Err(Error::external(format!("Invalid delay, it must be a finite number of at most {} ms", MAX_DELAY_MS)));
+    }
+    Ok(Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+}
+
+fn timer_delay(ms: f64, repeat: bool) -> Result<Duration> {
+    let delay = delay_from_ms(ms)?;
+    Ok(if repeat { delay.max(Duration::from_millis(1)) } else { delay })
+}
+
+fn due_in(delay: Duration) -> Result<Instant> {
+    Instant::now().checked_add(delay).ok_or_else(|| Error::external("Delay is too long"))
 }
 
 impl<'lua> FromLua<'lua> for Document {
@@ -89,73 +792,161 @@ impl<'lua> FromLua<'lua> for Document {
     }
 }
 
+fn table_to_path(table: Table) -> Result<VecDeque<usize>> {
+    let mut path = VecDeque::new();
+    for part in table.sequence_values::<usize>() {
+        match part {
+            Ok(index) => path.push_back(index),
+            Err(_) => {
+                return Err(Error::external("Path has non usize elements"));
+            }
+        }
+    }
+    Ok(path)
+}
+
 impl UserData for Document {
     fn add_methods<'lua, M: mlua::prelude::LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get_text", |_, this, path_table: Table| {
+            let path = table_to_path(path_table)?;
+            let mut page = this.page.try_borrow_mut().map_err(|_| Error::external("Document is busy"))?;
+            page.get_path_text(path).map_err(Error::external)
+        });
+        methods.add_method("get_attr", |_, this, (path_table, attr): (Table, String)| {
+            let path = table_to_path(path_table)?;
+            let mut page = this.page.try_borrow_mut().map_err(|_| Error::external("Document is busy"))?;
+            page.get_path_attr(path, &attr).map_err(Error::external)
+        });
         methods.add_method("set_text", |_, this, (path_table, text): (Table, String)| {
-            let mut path: VecDeque<usize> = VecDeque::new();
-            for part in path_table.sequence_values::<usize>() {
-                match part {
-                    Ok(index) => path.push_back(index),
-                    Err(_) => {
-                        return Err(Error::external("Path has non usize elements"));
-                    }
-                }
+            let path = table_to_path(path_table)?;
+            this.edit_page(|page, errors| page.set_path_text(path, text, errors))
+        });
+        methods.add_method("set_inner", |_, this, (path_table, rml): (Table, String)| {
+            let path = table_to_path(path_table)?;
+            this.edit_page(|page, errors| page.set_path_rml(path, &rml, errors))
+        });
+        methods.add_method("set_attr", |_, this, (path_table, attr, value): (Table, String, String)| {
+            let path = table_to_path(path_table)?;
+            this.edit_page(|page, errors| page.set_path_attr(path, attr, value, errors))
+        });
+        methods.add_method("log", |_, this, text: String| {
+            match this.changes_sender.send(DocumentChange::Log(text)) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(Error::external("Could not send document change")),
             }
-            match this.changes_sender.send(DocumentChange::SetText(path, text)) {
+        });
+        methods.add_method("set_location", |_, this, location: String| {
+            match this.changes_sender.send(DocumentChange::SetLocation(location)) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
         });
-        methods.add_method("set_inner", |_, this, (path_table, rml): (Table, String)| {
-            let mut path: VecDeque<usize> = VecDeque::new();
-            for part in path_table.sequence_values::<usize>() {
-                match part {
-                    Ok(index) => path.push_back(index),
-                    Err(_) => {
-                        return Err(Error::external("Path has non usize elements"));
-                    }
-                }
+        methods.add_method("open_url", |_, this, url: String| {
+            match this.changes_sender.send(DocumentChange::OpenLink(url)) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(Error::external("Could not send document change")),
             }
-            match this.changes_sender.send(DocumentChange::SetInner(path.into(), rml)) {
+        });
+        methods.add_method("set_title", |_, this, title: String| {
+            match this.changes_sender.send(DocumentChange::SetTitle(title)) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
         });
-        methods.add_method("set_attr", |_, this, (path_table, attr, value): (Table, String, String)| {
-            let mut path: VecDeque<usize> = VecDeque::new();
-            for part in path_table.sequence_values::<usize>() {
-                match part {
-                    Ok(index) => path.push_back(index),
-                    Err(_) => {
-                        return Err(Error::external("Path has non usize elements"));
-                    }
-                }
+        methods.add_method("alert", |lua, this, (message, callback): (String, Option<Function>)| {
+            let callback = callback.map(|callback| lua.create_registry_value(callback)).transpose()?;
+            match this.changes_sender.send(DocumentChange::ShowDialog(Dialog { message, kind: DialogKind::Alert, callback })) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(Error::external("Could not send document change")),
             }
-            match this.changes_sender.send(DocumentChange::SetAttr(path.into(), attr, value)) {
+        });
+        methods.add_method("confirm", |lua, this, (message, callback): (String, Function)| {
+            let callback = Some(lua.create_registry_value(callback)?);
+            match this.changes_sender.send(DocumentChange::ShowDialog(Dialog { message, kind: DialogKind::Confirm, callback })) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
         });
-        methods.add_method("log", |_, this, text: String| {
-            match this.changes_sender.send(DocumentChange::Log(text)) {
+        methods.add_method("prompt", |lua, this, (message, default, callback): (String, Option<String>, Function)| {
+            let callback = Some(lua.create_registry_value(callback)?);
+            let kind = DialogKind::Prompt(default.unwrap_or_default());
+            match this.changes_sender.send(DocumentChange::ShowDialog(Dialog { message, kind, callback })) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
         });
-        methods.add_method("set_location", |_, this, location: String| {
-            match this.changes_sender.send(DocumentChange::SetLocation(location)) {
+        methods.add_method("get", |_, this, path_table: Table| {
+            let path = table_to_path(path_table)?;
+            Ok(ElementHandle { document: this.clone(), path })
+        });
+        methods.add_method("on", |lua, this, (target, event, listener): (Value, Value, Option<Function>)| {
+            match (target, event, listener) {
+                (Value::Table(path_table), Value::String(event), Some(listener)) => {
+                    add_listener(lua, this, table_to_path(path_table)?, event.to_str()?.to_string(), listener)
+                }
+                (Value::String(event), Value::Function(listener), None) => {
+                    let event = event.to_str()?.to_string();
+                    if !PAGE_EVENTS.contains(&event.as_str()) {
+                        return Err(Error::external(format!("Unknown page event '{}'", event)));
+                    }
+                    let listener = lua.create_registry_value(listener)?;
+                    match this.changes_sender.send(DocumentChange::AddPageListener(event, listener)) {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err(Error::external("Could not send document change")),
+                    }
+                }
+                _ => Err(Error::external("Expected a path, an event and a function, or a page event and a function")),
+            }
+        });
+        methods.add_method("set_timeout", |lua, this, (callback, ms): (Function, f64)| {
+            this.add_timer(lua, callback, ms, false)
+        });
+        methods.add_method("set_interval", |lua, this, (callback, ms): (Function, f64)| {
+            this.add_timer(lua, callback, ms, true)
+        });
+        methods.add_method("clear_timer", |_, this, id: u64| {
+            match this.changes_sender.send(DocumentChange::ClearTimer(id)) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
         });
-        methods.add_method("open_url", |_, this, url: String| {
-            match this.changes_sender.send(DocumentChange::OpenLink(url)) {
+        methods.add_method("bind_key", |lua, this, (text, callback): (String, Function)| {
+            let sequence = parse_sequence(&text).map_err(Error::external)?;
+            if let Some(action) = viewer_conflict(&sequence) {
+                return Err(Error::external(format!("Can not bind '{}', the viewer uses it to {}", text, action)));
+            }
+            let binding = KeyBinding { text, sequence, callback: lua.create_registry_value(callback)? };
+            match this.changes_sender.send(DocumentChange::BindKey(binding)) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
         });
-        methods.add_method("set_title", |_, this, title: String| {
-            match this.changes_sender.send(DocumentChange::SetTitle(title)) {
+        methods.add_method("unbind_key", |_, this, text: String| {
+            let sequence = parse_sequence(&text).map_err(Error::external)?;
+            match this.changes_sender.send(DocumentChange::UnbindKey(sequence)) {
+                Ok(_) => Ok(()),
+                Err(_) => Err(Error::external("Could not send document change")),
+            }
+        });
+        methods.add_method("read_file", |_, this, path: String| {
+            let path = this.file_path(&path, Permission::ReadFiles)?;
+            fs::read_to_string(path).map_err(Error::external)
+        });
+        methods.add_method("write_file", |_, this, (path, text): (String, String)| {
+            let path = this.file_path(&path, Permission::WriteFiles)?;
+            fs::write(path, text).map_err(Error::external)
+        });
+        methods.add_method("toast", |_, this, (message, kind, duration): (String, Option<String>, Option<f64>)| {
+            let kind = match kind {
+                Some(kind) => ToastKind::parse(&kind).ok_or_else(|| Error::external(format!("Invalid toast kind '{}'", kind)))?,
+                None => ToastKind::Info,
+            };
+            let duration = duration.unwrap_or(3.0);
+            if !duration.is_finite() || duration <= 0.0 || duration > MAX_DELAY_MS / 1000.0 {
+                return Err(Error::external(format!("Invalid toast duration, it must be a positive number of at most {} seconds", MAX_DELAY_MS / 1000.0)));
+            }
+            let toast = Toast { message, kind, duration, expires: None };
+            match this.changes_sender.send(DocumentChange::ShowToast(toast)) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(Error::external("Could not send document change")),
             }
@@ -164,11 +955,103 @@ impl UserData for Document {
 }
 
 pub enum DocumentChange {
-    SetText(VecDeque<usize>, String),
-    SetInner(VecDeque<usize>, String),
     Log(String),
+    LogError(String),
     SetLocation(String),
     OpenLink(String),
-    SetAttr(VecDeque<usize>, String, String),
     SetTitle(String),
-}
\ No newline at end of file
+    ShowDialog(Dialog),
+    ShowToast(Toast),
+    AddListener(VecDeque<usize>, String, RegistryKey),
+    AddPageListener(String, RegistryKey),
+    BindKey(KeyBinding),
+    UnbindKey(Vec<KeyboardShortcut>),
+    SetTimer(Timer),
+    ClearTimer(u64),
+    WaitTimer(u64, RegistryKey),
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_document(lua: &Lua) -> Table<'_> {
+        let raw: Table = lua.load(r#"
+            local raw = { alerts = {} }
+            function raw:alert(message, callback)
+                table.insert(self.alerts, message)
+            end
+            return raw
+        "#).eval().unwrap();
+        let document = load_document(lua, raw).unwrap();
+        lua.globals().set("document", document.clone()).unwrap();
+        document
+    }
+
+    fn run(lua: &Lua, code: &str) -> Result<Option<Suspended>> {
+        start(lua, &Deadline::default(), Value::Function(lua.load(code).into_function()?), ())
+    }
+
+    #[test]
+    fn dialogs_wait_in_scripts() {
+        let lua = sandboxed_lua().unwrap();
+        test_document(&lua);
+        let suspended = run(&lua, "document:confirm('Sure?')").unwrap().unwrap();
+        assert!(matches!(suspended.wait, Wait::Dialog(message, DialogKind::Confirm) if message == "Sure?"));
+    }
+
+    #[test]
+    fn alerts_in_page_coroutines_are_shown_right_away() {
+        let lua = sandboxed_lua().unwrap();
+        let document = test_document(&lua);
+        let suspended = run(&lua, r#"
+            local thread = coroutine.create(function() document:alert("Hi") return "done" end)
+            assert(select(2, coroutine.resume(thread)) == "done")
+        "#).unwrap();
+        assert!(suspended.is_none());
+        let alerts: Vec<String> = document.get::<_, Table>("alerts").unwrap().sequence_values().collect::<Result<_>>().unwrap();
+        assert_eq!(alerts, ["Hi"]);
+    }
+
+    #[test]
+    fn waits_in_page_coroutines_are_errors() {
+        let lua = sandboxed_lua().unwrap();
+        test_document(&lua);
+        for call in ["document:confirm('Sure?')", "document:prompt('Name?')", "document:sleep(10)", "document:wait_timer(1)"] {
+            let code = format!("local ok, why = coroutine.resume(coroutine.create(function() {} end)) assert(not ok, 'waited') error(why, 0)", call);
+            let why = run(&lua, &code).err().unwrap().to_string();
+            assert!(why.contains("not in a coroutine made by the page"), "{}: {}", call, why);
+        }
+    }
+
+    #[test]
+    fn converts_delays() {
+        assert_eq!(delay_from_ms(0.0).unwrap(), Duration::ZERO);
+        assert_eq!(delay_from_ms(1500.0).unwrap(), Duration::from_millis(1500));
+        assert_eq!(delay_from_ms(-20.0).unwrap(), Duration::ZERO);
+        assert_eq!(delay_from_ms(MAX_DELAY_MS).unwrap(), Duration::from_millis(MAX_DELAY_MS as u64));
+    }
+
+    #[test]
+    fn rejects_invalid_delays() {
+        for ms in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, MAX_DELAY_MS + 1.0, f64::MAX] {
+            assert!(delay_from_ms(ms).is_err(), "{}", ms);
+        }
+    }
+
+    #[test]
+    fn intervals_wait_at_least_a_millisecond() {
+        assert_eq!(timer_delay(0.0, false).unwrap(), Duration::ZERO);
+        assert_eq!(timer_delay(0.0, true).unwrap(), Duration::from_millis(1));
+        assert_eq!(timer_delay(-5.0, true).unwrap(), Duration::from_millis(1));
+        assert_eq!(timer_delay(10.0, true).unwrap(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn timers_reject_invalid_delays() {
+        for repeat in [false, true] {
+            for ms in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                assert!(timer_delay(ms, repeat).is_err(), "{} {}", ms, repeat);
+            }
+        }
+    }
+}
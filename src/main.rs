@@ -4,6 +4,15 @@ mod app;
 mod parser;
 mod elements;
 mod lua;
+mod style;
+mod highlight;
+mod text;
+mod theme;
+mod dialogs;
+mod keys;
+mod sandbox;
+mod scripts;
+mod console;
 
 fn main() {
     env_logger::init();
@@ -1,141 +1,383 @@
-use std::{collections::VecDeque, fs};
+use std::{collections::VecDeque, fs, path::{Path, PathBuf}, rc::Rc};
+use ab_glyph::FontRef;
 use anyhow::anyhow;
-use eframe::egui::{Layout, TextBuffer, Ui};
-use crate::{elements::{Button, Div, Divider, FakeLink, Heading, Link, Paragraph, Space, WebLink}, lua::Executer};
+use mlua::RegistryKey;
+use eframe::egui::{Area, Color32, Event as InputEvent, Id, Key, LayerId, Layout, Modifiers, Order, PointerButton, Rect, Response, Sense, TextBuffer, Ui, Vec2, Visuals};
+use crate::{elements::{Button, Cell, CodeBlock, Details, Div, Divider, FakeLink, Grid, Heading, Justify, Link, List, ListItem, Paragraph, Position, Scroll, Space, Tab, Tabs, WebLink}, lua::{Action, Event, Executer, Handler, Origin, SCRIPT_EVENTS}, sandbox::{resolve_path, Permission}, scripts::Script, style::{style_for_state, Media, PseudoState, Selectable, StateRule, Style, Stylesheet}, text::{Span, SpanFormat}, theme::{builtin_theme, parse_theme}};
 
-pub type Elements = Vec<Box<dyn Element>>;
+pub type Elements = Vec<Node>;
+
+#[derive(Default)]
+pub struct Errors(pub Vec<String>);
+
+impl Errors {
+    pub fn log_error(&mut self, msg: impl ToString) {
+        self.0.push(msg.to_string());
+    }
+}
+
+const LAYERS_ID: &str = "absolute_layers";
 
 pub struct Page {
     pub title: String,
     body: Elements,
-    pub scripts: Vec<String>,
+    pub scripts: Vec<Script>,
+    stylesheet: Stylesheet,
+    pub theme: Option<Visuals>,
+    pub fonts: Vec<(String, Vec<u8>)>,
+    pub dir: PathBuf,
+    pub source: Rc<str>,
+    pub permissions: Vec<Permission>,
+    media_state: Vec<bool>,
+    needs_restyle: bool,
 }
 
 impl Page {
     pub fn render(&mut self, ui: &mut Ui, executer: &mut Executer) {
-        for element in &mut self.body {
-            element.render(ui, Style::default(), executer);
+        let media_state = self.stylesheet.media_state(ui.available_width());
+        if media_state != self.media_state {
+            self.media_state = media_state;
+            self.needs_restyle = true;
+        }
+        if self.needs_restyle {
+            self.restyle(ui.available_width());
+        }
+        executer.reset_events();
+        for node in &mut self.body {
+            node.render(ui, &Style::default(), executer);
+        }
+        let mut layers = ui.ctx().data_mut(|data| data.remove_temp::<Vec<(i32, LayerId)>>(Id::new(LAYERS_ID))).unwrap_or_default();
+        layers.sort_by_key(|(z, _)| *z);
+        for (_, layer) in layers {
+            ui.ctx().move_to_top(layer);
         }
     }
 
-    pub fn set_path_text(&mut self, mut path: VecDeque<usize>, text: String, executer: &mut Executer) {
-        match path.pop_front() {
-            Some(index) => {
-                match self.body.get_mut(index) {
-                    Some(element) => {
-                        if path.len() == 0 {
-                            element.set_text(text, executer);
-                        } else {
-                            element.set_path_text(path, text, executer);
-                        }
-                    }
-                    None => {
-                        executer.log_error("Invalid path");
-                    }
-                }
+    pub fn get_path_text(&mut self, path: VecDeque<usize>) -> Result<String, &'static str> {
+        element_at_path(&mut self.body, path)?.get_text().ok_or("Element does not have text")
+    }
+
+    pub fn set_path_text(&mut self, path: VecDeque<usize>, text: String, errors: &mut Errors) {
+        match element_at_path(&mut self.body, path) {
+            Ok(element) => element.set_text(text, errors),
+            Err(why) => errors.log_error(why),
+        }
+    }
+
+    pub fn set_path_inner(&mut self, path: VecDeque<usize>, inner: Elements, errors: &mut Errors) {
+        self.needs_restyle = true;
+        match element_at_path(&mut self.body, path) {
+            Ok(element) => element.set_inner(inner, errors),
+            Err(why) => errors.log_error(why),
+        }
+    }
+
+    pub fn set_path_rml(&mut self, path: VecDeque<usize>, rml: &str, errors: &mut Errors) {
+        match parse_string(rml) {
+            Ok(inner) => self.set_path_inner(path, inner, errors),
+            Err(why) => errors.log_error(why),
+        }
+    }
+
+    pub fn add_path_listener(&mut self, path: VecDeque<usize>, event: String, listener: RegistryKey, executer: &mut Executer) {
+        match element_at_path(&mut self.body, path) {
+            Ok(element) => Rc::make_mut(&mut element.handlers).push(Handler { event, action: Action::Function(Rc::new(listener)) }),
+            Err(why) => executer.log_error(why),
+        }
+    }
+
+    pub fn get_path_attr(&mut self, path: VecDeque<usize>, attr: &str) -> Result<String, &'static str> {
+        element_at_path(&mut self.body, path)?.get_attr(attr).ok_or("Element does not have attribute")
+    }
+
+    pub fn set_path_attr(&mut self, path: VecDeque<usize>, attr: String, value: String, errors: &mut Errors) {
+        if attr == "class" || attr == "id" || attr == "style" {
+            self.needs_restyle = true;
+        }
+        match element_at_path(&mut self.body, path) {
+            Ok(element) => element.set_attr(attr, value, errors),
+            Err(why) => errors.log_error(why),
+        }
+    }
+}
+
+impl Page {
+    fn restyle(&mut self, width: f32) {
+        restyle_elements(&mut self.body, &self.stylesheet, &mut vec![], width);
+        self.needs_restyle = false;
+    }
+}
+
+fn restyle_elements(elements: &mut Elements, stylesheet: &Stylesheet, ancestors: &mut Vec<Selectable>, width: f32) {
+    for node in elements {
+        node.rules = stylesheet.rules_for(&node.selectable, ancestors, width);
+        node.element.set_list_depth(ancestors.iter().filter(|ancestor| ancestor.tag == "ul" || ancestor.tag == "ol").count());
+        if let Some(children) = node.element.children_mut() {
+            ancestors.push(node.selectable.clone());
+            restyle_elements(children, stylesheet, ancestors, width);
+            ancestors.pop();
+        }
+    }
+}
+
+fn element_at_path(elements: &mut Elements, mut path: VecDeque<usize>) -> Result<&mut Node, &'static str> {
+    let index = path.pop_front().ok_or("Empty path")?;
+    let node = elements.get_mut(index).ok_or("Invalid path")?;
+    if path.is_empty() {
+        Ok(node)
+    } else {
+        match node.element.children_mut() {
+            Some(children) => element_at_path(children, path),
+            None => Err("Element is not a container"),
+        }
+    }
+}
+
+pub struct Node {
+    pub selectable: Selectable,
+    pub style: Style,
+    pub rules: Vec<StateRule>,
+    pub state: PseudoState,
+    pub disabled: bool,
+    pub grow: f32,
+    pub cell: Cell,
+    pub position: Position,
+    pub handlers: Rc<Vec<Handler>>,
+    pub origin: Origin,
+    pub element: Box<dyn Element>,
+}
+
+impl Node {
+    pub fn new(tag: &str, element: Box<dyn Element>) -> Self {
+        let selectable = Selectable { tag: tag.to_string(), ..Default::default() };
+        Self { selectable, style: Style::default(), rules: vec![], state: PseudoState::default(), disabled: false, grow: 0.0, cell: Cell::default(), position: Position::default(), handlers: Rc::default(), origin: Origin::default(), element }
+    }
+
+    pub fn render(&mut self, ui: &mut Ui, parent_style: &Style, executer: &mut Executer) -> Response {
+        let placeholder = Rect::from_min_size(ui.cursor().min, Vec2::ZERO);
+        if !self.position.absolute {
+            return self.render_in_flow(ui, parent_style, executer);
+        } else if !ui.is_visible() {
+            return ui.interact(placeholder, ui.next_auto_id(), Sense::hover());
+        }
+        let clip_rect = ui.clip_rect();
+        let id = ui.next_auto_id();
+        ui.skip_ahead_auto_ids(1);
+        let area = Area::new(id)
+            .order(Order::Middle)
+            .movable(false)
+            .constrain(false)
+            .fixed_pos(ui.max_rect().min + Vec2::new(self.position.x, self.position.y))
+            .show(ui.ctx(), |ui| {
+                ui.set_clip_rect(clip_rect);
+                self.render_in_flow(ui, parent_style, executer)
+            });
+        ui.ctx().data_mut(|data| data.get_temp_mut_or_default::<Vec<(i32, LayerId)>>(Id::new(LAYERS_ID)).push((self.position.z, area.response.layer_id)));
+        ui.interact(placeholder, ui.next_auto_id(), Sense::hover())
+    }
+
+    fn render_in_flow(&mut self, ui: &mut Ui, parent_style: &Style, executer: &mut Executer) -> Response {
+        let measuring = !ui.is_visible();
+        let state = PseudoState { disabled: self.disabled || (!ui.is_enabled() && !measuring), ..self.state };
+        let style = style_for_state(&self.rules, &self.style, state).inherit(parent_style);
+        if style.hidden == Some(true) {
+            return ui.interact(Rect::from_min_size(ui.cursor().min, Vec2::ZERO), ui.next_auto_id(), Sense::hover());
+        }
+        executer.push_handlers(&self.origin, self.handlers.clone());
+        let (response, element_response) = ui.add_enabled_ui(!self.disabled, |ui| match style.frame() {
+            Some(frame) => {
+                let response = frame.show(ui, |ui| self.element.render(ui, &style, executer));
+                (response.response, response.inner)
             }
             None => {
-                executer.log_error("Empty path");
+                let response = self.element.render(ui, &style, executer);
+                (response.clone(), response)
+            }
+        }).inner;
+        let hover = ui.rect_contains_pointer(response.rect);
+        let new_state = PseudoState {
+            hover,
+            active: element_response.is_pointer_button_down_on() || (hover && ui.input(|input| input.pointer.primary_down())),
+            focus: element_response.has_focus(),
+            disabled: false,
+        };
+        if !measuring {
+            let old_state = self.state;
+            if new_state != self.state {
+                self.state = new_state;
+                ui.ctx().request_repaint();
             }
+            self.fire_events(ui, old_state, response.rect, &element_response, executer);
         }
+        executer.pop_handlers();
+        response
     }
 
-    pub fn set_path_inner(&mut self, mut path: VecDeque<usize>, inner: Elements, executer: &mut Executer) {
-        match path.pop_front() {
-            Some(index) => {
-                match self.body.get_mut(index) {
-                    Some(element) => {
-                        if path.len() == 0 {
-                            element.set_inner(inner, executer);
-                        } else {
-                            element.set_path_inner(path, inner, executer);
-                        }
-                    }
-                    None => {
-                        executer.log_error("Invalid path");
-                    }
+    fn fire_events(&self, ui: &Ui, old_state: PseudoState, rect: Rect, element_response: &Response, executer: &mut Executer) {
+        let (pointer, modifiers) = ui.input(|input| (input.pointer.interact_pos(), input.modifiers));
+        let event = |kind, bubbles| Event { kind, pos: pointer.map(|pos| pos - rect.min), modifiers, key: None, bubbles };
+        if self.state.hover != old_state.hover {
+            executer.fire(event(if self.state.hover { "hover" } else { "leave" }, false));
+        }
+        if self.state.focus != old_state.focus {
+            executer.fire(event(if self.state.focus { "focus" } else { "blur" }, false));
+        }
+        if ui.is_enabled() && !self.disabled {
+            let hover = self.state.hover;
+            let (clicked, double_clicked, secondary_clicked) = ui.input(|input| {
+                (input.pointer.primary_clicked(), input.pointer.button_double_clicked(PointerButton::Primary), input.pointer.secondary_clicked())
+            });
+            let pointer_events = [
+                ("click", element_response.clicked() || (hover && clicked)),
+                ("doubleclick", element_response.double_clicked() || (hover && double_clicked)),
+                ("contextmenu", element_response.secondary_clicked() || (hover && secondary_clicked)),
+            ];
+            for (kind, happened) in pointer_events {
+                if happened && executer.claim_event(kind) {
+                    executer.fire(event(kind, true));
                 }
             }
-            None => {
-                executer.log_error("Empty path");
+        }
+        if element_response.changed() {
+            executer.fire(event(self.element.change_event(), true));
+        }
+        if self.state.focus {
+            let keys: Vec<(Key, Modifiers)> = ui.input(|input| input.events.iter().filter_map(|event| match event {
+                InputEvent::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                _ => None,
+            }).collect());
+            for (key, modifiers) in keys {
+                executer.fire(Event { kind: "keydown", pos: None, modifiers, key: Some(key.name()), bubbles: true });
             }
         }
     }
 
-    pub fn set_path_attr(&mut self, mut path: VecDeque<usize>, attr: String, value: String, executer: &mut Executer) {
-        match path.pop_front() {
-            Some(index) => {
-                match self.body.get_mut(index) {
-                    Some(element) => {
-                        if path.len() == 0 {
-                            element.set_attr(attr, value, executer);
-                        } else {
-                            element.set_path_attr(path, attr, value, executer);
-                        }
-                    }
-                    None => {
-                        executer.log_error("Invalid path");
-                    }
+    pub fn set_inner(&mut self, new: Elements, errors: &mut Errors) {
+        self.element.set_inner(new, errors);
+    }
+
+    pub fn get_text(&self) -> Option<String> {
+        self.element.get_text()
+    }
+
+    pub fn set_text(&mut self, text: String, errors: &mut Errors) {
+        self.element.set_text(text, errors);
+    }
+
+    pub fn get_attr(&self, attr: &str) -> Option<String> {
+        match attr {
+            "style" => Some(self.style.declarations()),
+            "class" => Some(self.selectable.classes.join(" ")),
+            "disabled" => Some(self.disabled.to_string()),
+            "grow" => Some(self.grow.to_string()),
+            "colspan" | "rowspan" | "halign" | "valign" => self.cell.get_attr(attr),
+            "position" | "x" | "y" | "z" => self.position.get_attr(attr),
+            "id" => self.selectable.id.clone(),
+            _ if attr.strip_prefix("on").is_some_and(|event| SCRIPT_EVENTS.contains(&event)) => self.handlers.iter().find_map(|handler| match &handler.action {
+                Action::Script(code, _) if attr[2..] == handler.event => Some(code.clone()),
+                _ => None,
+            }),
+            _ => self.element.get_attr(attr),
+        }
+    }
+
+    pub fn set_attr(&mut self, attr: String, value: String, errors: &mut Errors) {
+        match attr.as_str() {
+            "style" => match Style::parse(&value) {
+                Ok(style) => self.style = style,
+                Err(why) => errors.log_error(why),
+            },
+            "class" => self.selectable.classes = value.split_whitespace().map(str::to_string).collect(),
+            "id" => self.selectable.id = if value.is_empty() { None } else { Some(value) },
+            "disabled" => match value.parse() {
+                Ok(disabled) => self.disabled = disabled,
+                Err(_) => errors.log_error(format!("Invalid disabled '{}'", value)),
+            },
+            "grow" => match value.parse() {
+                Ok(grow) => self.grow = grow,
+                Err(_) => errors.log_error(format!("Invalid grow '{}'", value)),
+            },
+            "colspan" | "rowspan" | "halign" | "valign" => {
+                if let Err(why) = self.cell.set_attr(&attr, &value) {
+                    errors.log_error(why);
                 }
             }
-            None => {
-                executer.log_error("Empty path");
+            "position" | "x" | "y" | "z" => {
+                if let Err(why) = self.position.set_attr(&attr, &value) {
+                    errors.log_error(why);
+                }
             }
+            _ if attr.strip_prefix("on").is_some_and(|event| SCRIPT_EVENTS.contains(&event)) => {
+                let handlers = Rc::make_mut(&mut self.handlers);
+                handlers.retain(|handler| !(handler.event == attr[2..] && matches!(handler.action, Action::Script(..))));
+                handlers.push(Handler { event: attr[2..].to_string(), action: Action::Script(value, self.origin.clone()) });
+            }
+            _ => self.element.set_attr(attr, value, errors),
         }
     }
 }
 
 pub trait Element {
-    fn render(&mut self, ui: &mut Ui, style: Style, executer: &mut Executer);
+    fn render(&mut self, ui: &mut Ui, style: &Style, executer: &mut Executer) -> Response;
 
-    fn set_inner(&mut self, new: Elements, executer: &mut Executer) {
-        executer.log_error("Element is not a container");
+    fn children_mut(&mut self) -> Option<&mut Elements> {
+        None
     }
 
-    fn set_text(&mut self, text: String, executer: &mut Executer) {
-        executer.log_error("Element does not have text");
+    fn set_list_depth(&mut self, _depth: usize) {}
+
+    fn set_inner(&mut self, _new: Elements, errors: &mut Errors) {
+        errors.log_error("Element is not a container");
     }
 
-    fn set_attr(&mut self, attr: String, value: String, executer: &mut Executer) {
-        executer.log_error("Element does not have attributes");
+    fn get_text(&self) -> Option<String> {
+        None
     }
 
-    fn set_path_inner(&mut self, path: VecDeque<usize>, new: Elements, executer: &mut Executer) {
-        executer.log_error("Element is not a container");
+    fn set_text(&mut self, _text: String, errors: &mut Errors) {
+        errors.log_error("Element does not have text");
     }
 
-    fn set_path_text(&mut self, path: VecDeque<usize>, text: String, executer: &mut Executer) {
-        executer.log_error("Element is not a container");
+    fn get_attr(&self, _attr: &str) -> Option<String> {
+        None
     }
 
-    fn set_path_attr(&mut self, path: VecDeque<usize>, attr: String, value: String, executer: &mut Executer) {
-        executer.log_error("Element is not a container");
+    fn set_attr(&mut self, _attr: String, _value: String, errors: &mut Errors) {
+        errors.log_error("Element does not have attributes");
     }
-}
 
-#[derive(Default, Clone, Copy)]
-pub struct Style {
-    
+    fn change_event(&self) -> &'static str {
+        "change"
+    }
 }
 
 pub fn parse_page(path: &str) -> anyhow::Result<Page> {
     let string = fs::read_to_string(path)?;
-    let tags = parse_tags(&string)?;
-    let page = tags_to_page(tags)?;
-    Ok(page)
+    let source: Rc<str> = Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy().into());
+    let tags = parse_tags(&string, &source)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    tags_to_page(tags, dir, source)
 }
 
 pub fn parse_string(string: &str) -> anyhow::Result<Elements> {
-    let tags = parse_tags(&string)?;
+    let tags = parse_tags(string, &Rc::from(INNER_SOURCE))?;
     let elements = tags_to_elements(&tags)?;
     Ok(elements)
 }
 
+const INNER_SOURCE: &str = "set_inner";
+const TEXT_TAGS: [&str; 3] = ["p", "h", "li"];
+const INLINE_TAGS: [&str; 7] = ["b", "i", "u", "s", "code", "span", "link"];
+const RAW_TEXT_TAGS: [&str; 3] = ["script", "style", "pre"];
+
 #[derive(Debug, Clone)]
 struct Tag {
     name: String,
     attributes: Vec<(String, String)>,
     children: Vec<TagOrText>,
+    source: Rc<str>,
+    line: usize,
+    content_line: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -152,9 +394,22 @@ enum ParseState {
     AttributeEquals,
     AttributeString,
     TagOrText,
+    RawText,
 }
 
-fn parse_tags(string: &str) -> anyhow::Result<Vec<TagOrText>> {
+fn is_code_block(name: &str, attributes: &[(String, String)], parent: Option<&str>) -> bool {
+    name == "code" && (attributes.iter().any(|(attr, _)| attr == "lang") || !parent.is_some_and(|parent| TEXT_TAGS.contains(&parent) || INLINE_TAGS.contains(&parent)))
+}
+
+fn state_after_open(name: &str, attributes: &[(String, String)], parent: Option<&str>) -> ParseState {
+    if RAW_TEXT_TAGS.contains(&name) || is_code_block(name, attributes, parent) {
+        ParseState::RawText
+    } else {
+        ParseState::TagOrText
+    }
+}
+
+fn parse_tags(string: &str, source: &Rc<str>) -> anyhow::Result<Vec<TagOrText>> {
     let mut stack = vec![];
     let mut state = ParseState::TagOrText;
     let mut tag_name_buffer = "".to_string();
@@ -163,7 +418,12 @@ fn parse_tags(string: &str) -> anyhow::Result<Vec<TagOrText>> {
     let mut attributes = vec![];
     let mut text_buffer = "".to_string();
     let mut child_stack = vec![vec![]];
+    let mut line = 1;
+    let mut tag_line = 1;
     for chr in string.chars() {
+        if chr == '\n' {
+            line += 1;
+        }
         let mut new_state = state;
         match &state {
             ParseState::AttributeString => {
@@ -176,12 +436,19 @@ fn parse_tags(string: &str) -> anyhow::Result<Vec<TagOrText>> {
             }
             ParseState::Attribute => {
                 if chr == '>' {
-                    new_state = ParseState::TagOrText;
-                    stack.push((tag_name_buffer.take(), attributes.clone()));
+                    if !attribute_buffer.is_empty() {
+                        attributes.push((attribute_buffer.take(), "".to_string()));
+                    }
+                    new_state = state_after_open(&tag_name_buffer, &attributes, stack.last().map(|(name, ..): &(String, _, _, _)| name.as_str()));
+                    stack.push((tag_name_buffer.take(), attributes.clone(), tag_line, line));
                     child_stack.push(vec![]);
                     attributes.clear();
                 } else if chr == '=' {
                     new_state = ParseState::AttributeEquals;
+                } else if chr.is_whitespace() {
+                    if !attribute_buffer.is_empty() {
+                        attributes.push((attribute_buffer.take(), "".to_string()));
+                    }
                 } else {
                     attribute_buffer.push(chr);
                 }
@@ -195,11 +462,11 @@ fn parse_tags(string: &str) -> anyhow::Result<Vec<TagOrText>> {
             }
             ParseState::TagStart => {
                 if chr == '>' {
-                    new_state = ParseState::TagOrText;
-                    stack.push((tag_name_buffer.take(), vec![]));
+                    new_state = state_after_open(&tag_name_buffer, &[], stack.last().map(|(name, ..): &(String, _, _, _)| name.as_str()));
+                    stack.push((tag_name_buffer.take(), vec![], tag_line, line));
                     child_stack.push(vec![]);
                     attributes.clear();
-                } else if chr == ' ' {
+                } else if chr.is_whitespace() {
                     new_state = ParseState::Attribute;
                 } else if chr == '/' {
                     new_state = ParseState::TagEnd;
@@ -213,10 +480,10 @@ fn parse_tags(string: &str) -> anyhow::Result<Vec<TagOrText>> {
                         return Err(anyhow!("Closed tag '{}' without having opened it", tag_name_buffer));
                     }
                     tag_name_buffer.clear();
-                    let (name, attributes) = stack.pop().unwrap();
+                    let (name, attributes, line, content_line) = stack.pop().unwrap();
                     let children = child_stack.pop().unwrap();
                     let index = child_stack.len() - 1;
-                    child_stack[index].push(TagOrText::Tag(Tag { name, attributes, children }));
+                    child_stack[index].push(TagOrText::Tag(Tag { name, attributes, children, source: source.clone(), line, content_line }));
                     new_state = ParseState::TagOrText;
                 } else {
                     tag_name_buffer.push(chr);
@@ -224,47 +491,121 @@ fn parse_tags(string: &str) -> anyhow::Result<Vec<TagOrText>> {
             }
             ParseState::TagOrText => {
                 if chr == '<' {
-                    if text_buffer.len() != 0 && !text_buffer.chars().all(|c| c == ' ' || c == '\n' || c == '\r' || c == '\t') {
+                    let keeps_whitespace = stack.last().is_some_and(|(name, ..): &(String, _, _, _)| TEXT_TAGS.contains(&name.as_str()) || INLINE_TAGS.contains(&name.as_str()));
+                    if !text_buffer.is_empty() && (keeps_whitespace || !is_whitespace(&text_buffer)) {
                         let index = child_stack.len() - 1;
                         child_stack[index].push(TagOrText::Text(text_buffer.take()));
                     }
                     text_buffer.clear();
+                    tag_line = line;
                     new_state = ParseState::TagStart;
                 } else {
                     text_buffer.push(chr);
                 }
             }
+            ParseState::RawText => {
+                text_buffer.push(chr);
+                let (name, ..) = stack.last().unwrap();
+                if let Some(text) = text_buffer.strip_suffix(&format!("</{}>", name)) {
+                    let mut children = child_stack.pop().unwrap();
+                    if !text.is_empty() {
+                        children.push(TagOrText::Text(text.to_string()));
+                    }
+                    text_buffer.clear();
+                    let (name, attributes, line, content_line) = stack.pop().unwrap();
+                    let index = child_stack.len() - 1;
+                    child_stack[index].push(TagOrText::Tag(Tag { name, attributes, children, source: source.clone(), line, content_line }));
+                    new_state = ParseState::TagOrText;
+                }
+            }
         }
         state = new_state;
     }
     Ok(child_stack.pop().unwrap())
 }
 
-fn tags_to_page(tags: Vec<TagOrText>) -> anyhow::Result<Page> {
+fn tags_to_page(tags: Vec<TagOrText>, dir: &Path, source: Rc<str>) -> anyhow::Result<Page> {
     let mut title = "Untitled".to_string();
     let mut scripts = vec![];
-    if let Some(TagOrText::Tag(head)) = tags.get(0) {
+    let mut stylesheet = Stylesheet::default();
+    let mut theme = None;
+    let mut fonts = vec![];
+    let mut permissions = vec![];
+    if let Some(TagOrText::Tag(head)) = tags.first() {
         if head.name == "head" {
             for tag in &head.children {
-                match tag {
-                    TagOrText::Tag(tag) => {
-                        if tag.name == "title" {
-                            if let Some(TagOrText::Text(text)) = tag.children.get(0) {
-                                title = text.clone();
-                            } else {
-                                return Err(anyhow!("Empty title tag"));
-                            }
-                        } else if tag.name == "script" {
-                            if let Some(TagOrText::Text(text)) = tag.children.get(0) {
-                                scripts.push(text.clone());
-                            } else {
-                                return Err(anyhow!("Empty script tag"));
-                            }
+                if let TagOrText::Tag(tag) = tag {
+                    if tag.name == "title" {
+                        if let Some(TagOrText::Text(text)) = tag.children.first() {
+                            title = text.clone();
+                        } else {
+                            return Err(anyhow!("Empty title tag"));
+                        }
+                    } else if tag.name == "script" {
+                        if let Some(src) = get_attribute(tag, "src") {
+                            scripts.push(Script::File(src));
+                        } else if let Some(TagOrText::Text(text)) = tag.children.first() {
+                            scripts.push(Script::Inline { code: text.clone(), name: format!("{}:{} <script>", source, tag.line), line: tag.content_line });
                         } else {
-                            return Err(anyhow!("Unknown tag '{}' in head", tag.name));
+                            return Err(anyhow!("Empty script tag"));
                         }
+                    } else if tag.name == "style" {
+                        let media = get_attribute(tag, "media").map(|media| Media::parse(&media)).transpose()?;
+                        if let Some(TagOrText::Text(text)) = tag.children.first() {
+                            stylesheet.parse(text, media)?;
+                        }
+                    } else if tag.name == "theme" {
+                        theme = Some(match (get_attribute(tag, "src"), tag.children.first()) {
+                            (Some(src), _) => match resolve_path(dir, &src).and_then(|path| Ok(fs::read_to_string(path)?)) {
+                                Ok(text) => parse_theme(&text)?,
+                                Err(why) => return Err(anyhow!("Could not load theme '{}': {}", src, why)),
+                            },
+                            (None, Some(TagOrText::Text(name))) => match builtin_theme(name.trim()) {
+                                Some(visuals) => visuals,
+                                None => return Err(anyhow!("Unknown theme '{}'", name.trim())),
+                            },
+                            (None, _) => return Err(anyhow!("Empty theme tag")),
+                        });
+                    } else if tag.name == "font" {
+                        let Some(name) = get_attribute(tag, "name") else {
+                            return Err(anyhow!("No name attribute for font"));
+                        };
+                        let Some(src) = get_attribute(tag, "src") else {
+                            return Err(anyhow!("No src attribute for font '{}'", name));
+                        };
+                        let data = match resolve_path(dir, &src).and_then(|path| Ok(fs::read(path)?)) {
+                            Ok(data) => data,
+                            Err(why) => return Err(anyhow!("Could not load font '{}': {}", src, why)),
+                        };
+                        if FontRef::try_from_slice(&data).is_err() {
+                            return Err(anyhow!("Font '{}' is not a ttf or otf file", src));
+                        }
+                        fonts.push((name, data));
+                    } else if tag.name == "permission" {
+                        let Some(TagOrText::Text(name)) = tag.children.first() else {
+                            return Err(anyhow!("Empty permission tag"));
+                        };
+                        match Permission::parse(name.trim()) {
+                            Some(permission) => permissions.push(permission),
+                            None => return Err(anyhow!("Unknown permission '{}'", name.trim())),
+                        }
+                    } else if tag.name == "link" {
+                        let media = get_attribute(tag, "media").map(|media| Media::parse(&media)).transpose()?;
+                        if get_attribute(tag, "rel").as_deref() != Some("stylesheet") {
+                            return Err(anyhow!("Unsupported link rel in head"));
+                        }
+                        let src = match get_attribute(tag, "src").or_else(|| get_attribute(tag, "href")) {
+                            Some(src) => src,
+                            None => return Err(anyhow!("No src attribute for stylesheet link")),
+                        };
+                        let text = match resolve_path(dir, &src).and_then(|path| Ok(fs::read_to_string(path)?)) {
+                            Ok(text) => text,
+                            Err(why) => return Err(anyhow!("Could not load stylesheet '{}': {}", src, why)),
+                        };
+                        stylesheet.parse(&text, media)?;
+                    } else {
+                        return Err(anyhow!("Unknown tag '{}' in head", tag.name));
                     }
-                    _ => {}
                 }
             }
         } else {
@@ -275,17 +616,14 @@ fn tags_to_page(tags: Vec<TagOrText>) -> anyhow::Result<Page> {
     }
     let body = if let Some(TagOrText::Tag(body_tag)) = tags.get(1) {
         if body_tag.name == "body" {
-            match tags_to_elements(&body_tag.children) {
-                Ok(body) => body,
-                Err(why) => return Err(why),
-            }
+            tags_to_elements(&body_tag.children)?
         } else {
             return Err(anyhow!("Second tag is not body"));
         }
     } else {
         return Err(anyhow!("Second tag is not body"));
     };
-    Ok(Page { title, body, scripts })
+    Ok(Page { title, body, scripts, stylesheet, theme, fonts, dir: dir.to_path_buf(), source, permissions, media_state: vec![], needs_restyle: true })
 }
 
 fn tags_to_elements(tags: &Vec<TagOrText>) -> anyhow::Result<Elements> {
@@ -293,7 +631,7 @@ fn tags_to_elements(tags: &Vec<TagOrText>) -> anyhow::Result<Elements> {
     for tag in tags {
         match tag {
             TagOrText::Tag(tag) => {
-                elemets.push(tag_to_elemets(tag.clone())?);
+                elemets.push(tag_to_node(tag.clone())?);
             }
             _ => {
                 return Err(anyhow!("Text in body tag"));
@@ -303,15 +641,61 @@ fn tags_to_elements(tags: &Vec<TagOrText>) -> anyhow::Result<Elements> {
     Ok(elemets)
 }
 
+fn tag_to_node(tag: Tag) -> anyhow::Result<Node> {
+    let style = match get_attribute(&tag, "style") {
+        Some(style) => Style::parse(&style)?,
+        None => Style::default(),
+    };
+    let selectable = Selectable {
+        tag: tag.name.clone(),
+        id: get_attribute(&tag, "id"),
+        classes: get_attribute(&tag, "class").map_or(vec![], |class| class.split_whitespace().map(str::to_string).collect()),
+    };
+    let disabled = get_attribute(&tag, "disabled").is_some_and(|disabled| disabled != "false");
+    let grow = match get_attribute(&tag, "grow") {
+        Some(val) => match val.parse() {
+            Ok(grow) => grow,
+            Err(_) => return Err(anyhow!("Invalid grow '{}'", val)),
+        },
+        None => 0.0,
+    };
+    let mut cell = Cell::default();
+    for attr in ["colspan", "rowspan", "halign", "valign"] {
+        if let Some(value) = get_attribute(&tag, attr) {
+            cell.set_attr(attr, &value)?;
+        }
+    }
+    let selector = match (get_attribute(&tag, "id"), get_attribute(&tag, "class")) {
+        (Some(id), _) => format!("{}#{}", tag.name, id),
+        (None, Some(class)) => format!("{}.{}", tag.name, class.split_whitespace().collect::<Vec<_>>().join(".")),
+        (None, None) => tag.name.clone(),
+    };
+    let origin = Origin { source: tag.source.clone(), line: tag.line, selector: selector.into() };
+    let mut handlers = vec![];
+    for event in SCRIPT_EVENTS {
+        if let Some(code) = get_attribute(&tag, &format!("on{}", event)) {
+            handlers.push(Handler { event: event.to_string(), action: Action::Script(code, origin.clone()) });
+        }
+    }
+    let mut position = Position::default();
+    for attr in ["position", "x", "y", "z"] {
+        if let Some(value) = get_attribute(&tag, attr) {
+            position.set_attr(attr, &value)?;
+        }
+    }
+    let element = tag_to_elemets(tag)?;
+    Ok(Node { selectable, style, rules: vec![], state: PseudoState::default(), disabled, grow, cell, position, handlers: Rc::new(handlers), origin, element })
+}
+
 fn tag_to_elemets(tag: Tag) -> anyhow::Result<Box<dyn Element>> {
     Ok(match tag.name.as_str() {
         "h" => {
-            let text = get_text(tag)?;
-            Box::new(Heading { text })
+            let spans = get_spans(tag)?;
+            Box::new(Heading { spans })
         }
         "p" => {
-            let text = get_text(tag)?;
-            Box::new(Paragraph { text })
+            let spans = get_spans(tag)?;
+            Box::new(Paragraph { spans })
         }
         "button" => {
             let on_click = get_attribute(&tag, "onclick").unwrap_or("".to_string());
@@ -321,18 +705,38 @@ fn tag_to_elemets(tag: Tag) -> anyhow::Result<Box<dyn Element>> {
         "div" => {
             let direction = get_attribute(&tag, "direction");
             let align = get_attribute(&tag, "align");
+            let width = parse_size(get_attribute(&tag, "width"))?;
+            let height = parse_size(get_attribute(&tag, "height"))?;
+            let max_height = parse_size(get_attribute(&tag, "max-height"))?;
+            let scroll = match get_attribute(&tag, "scroll") {
+                Some(val) => match Scroll::parse(&val) {
+                    Some(scroll) => scroll,
+                    None => return Err(anyhow!("Invalid scroll '{}'", val)),
+                },
+                None => Scroll::None,
+            };
+            let stretch = align.as_deref() == Some("stretch");
+            let wrap = get_attribute(&tag, "wrap").is_some_and(|wrap| wrap != "false");
+            let gap = parse_size(get_attribute(&tag, "gap"))?;
+            let justify = match get_attribute(&tag, "justify") {
+                Some(val) => match Justify::parse(&val) {
+                    Some(justify) => justify,
+                    None => return Err(anyhow!("Invalid justify '{}'", val)),
+                },
+                None => Justify::Start,
+            };
             let mut inner = vec![];
             for tag in tag.children {
                 if let TagOrText::Tag(tag) = tag {
-                    inner.push(tag_to_elemets(tag)?);
+                    inner.push(tag_to_node(tag)?);
                 } else {
                     return Err(anyhow!("Text in div"));
                 }
             }
-            if direction.is_none() && align.is_none() {
-                Box::new(Div { inner, layout: None })
+            let layout = if direction.is_none() && align.is_none() {
+                None
             } else {
-                let layout = Layout {
+                Some(Layout {
                     main_dir: match direction {
                         Some(val) => {
                             match val.as_str() {
@@ -350,16 +754,112 @@ fn tag_to_elemets(tag: Tag) -> anyhow::Result<Box<dyn Element>> {
                             match val.as_str() {
                                 "center" => eframe::egui::Align::Center,
                                 "max" => eframe::egui::Align::Max,
-                                "min" => eframe::egui::Align::Min,
+                                "min" | "stretch" => eframe::egui::Align::Min,
                                 _ => return Err(anyhow!("Invalid align '{}'", val)),
                             }
                         }
                         None => eframe::egui::Align::Min,
                     },
                     ..Default::default()
-                };
-                Box::new(Div { inner, layout: Some(layout) })
+                })
+            };
+            Box::new(Div { inner, layout, width, height, max_height, scroll, wrap, gap, justify, stretch, sizes: vec![] })
+        }
+        "ul" | "ol" => {
+            let ordered = tag.name == "ol";
+            let start = match get_attribute(&tag, "start") {
+                Some(val) => match val.parse() {
+                    Ok(start) => start,
+                    Err(_) => return Err(anyhow!("Invalid start '{}'", val)),
+                },
+                None => 1,
+            };
+            let mut inner = vec![];
+            for tag in tag.children {
+                match tag {
+                    TagOrText::Tag(tag) if tag.name == "li" => inner.push(tag_to_node(tag)?),
+                    TagOrText::Tag(tag) => return Err(anyhow!("Unexpected tag '{}' in list", tag.name)),
+                    TagOrText::Text(_) => return Err(anyhow!("Text in list")),
+                }
             }
+            Box::new(List { inner, ordered, start, depth: 0 })
+        }
+        "li" => {
+            let mut inner: Elements = vec![];
+            let mut inline = vec![];
+            for child in tag.children {
+                match child {
+                    TagOrText::Tag(tag) if !INLINE_TAGS.contains(&tag.name.as_str()) || is_code_block(&tag.name, &tag.attributes, Some("li")) => {
+                        push_inline_paragraph(&mut inline, &mut inner)?;
+                        inner.push(tag_to_node(tag)?);
+                    }
+                    child => inline.push(child),
+                }
+            }
+            push_inline_paragraph(&mut inline, &mut inner)?;
+            Box::new(ListItem { inner })
+        }
+        "code" | "pre" => {
+            let lang = get_attribute(&tag, "lang");
+            let code = match tag.children.first() {
+                Some(TagOrText::Text(text)) => trim_code(text),
+                _ => "".to_string(),
+            };
+            Box::new(CodeBlock { code, lang })
+        }
+        "details" => {
+            let title = get_attribute(&tag, "title").unwrap_or("Details".to_string());
+            let open = get_attribute(&tag, "open").is_some_and(|open| open != "false");
+            let on_toggle = get_attribute(&tag, "ontoggle").unwrap_or("".to_string());
+            let inner = tags_to_elements(&tag.children)?;
+            Box::new(Details { title, open, on_toggle, inner })
+        }
+        "tabs" => {
+            let selected = match get_attribute(&tag, "selected") {
+                Some(val) => match val.parse() {
+                    Ok(selected) => selected,
+                    Err(_) => return Err(anyhow!("Invalid selected '{}'", val)),
+                },
+                None => 0,
+            };
+            let on_change = get_attribute(&tag, "onchange").unwrap_or("".to_string());
+            let mut inner = vec![];
+            for tag in tag.children {
+                match tag {
+                    TagOrText::Tag(tag) if tag.name == "tab" => inner.push(tag_to_node(tag)?),
+                    TagOrText::Tag(tag) => return Err(anyhow!("Unexpected tag '{}' in tabs", tag.name)),
+                    TagOrText::Text(_) => return Err(anyhow!("Text in tabs")),
+                }
+            }
+            Box::new(Tabs { inner, selected, on_change })
+        }
+        "tab" => {
+            let title = match get_attribute(&tag, "title") {
+                Some(title) => title,
+                None => return Err(anyhow!("No title attribute for tab")),
+            };
+            let inner = tags_to_elements(&tag.children)?;
+            Box::new(Tab { title, inner })
+        }
+        "grid" => {
+            let columns = match get_attribute(&tag, "columns") {
+                Some(val) => match val.parse() {
+                    Ok(columns) if columns > 0 => columns,
+                    _ => return Err(anyhow!("Invalid columns '{}'", val)),
+                },
+                None => 1,
+            };
+            let gap = parse_size(get_attribute(&tag, "gap"))?;
+            let min_column_width = parse_size(get_attribute(&tag, "min-column-width"))?;
+            let mut inner = vec![];
+            for tag in tag.children {
+                if let TagOrText::Tag(tag) = tag {
+                    inner.push(tag_to_node(tag)?);
+                } else {
+                    return Err(anyhow!("Text in grid"));
+                }
+            }
+            Box::new(Grid { inner, columns, gap, min_column_width, heights: vec![] })
         }
         "space" => Box::new(Space),
         "divider" => Box::new(Divider),
@@ -393,8 +893,121 @@ fn tag_to_elemets(tag: Tag) -> anyhow::Result<Box<dyn Element>> {
     })
 }
 
+fn push_inline_paragraph(inline: &mut Vec<TagOrText>, elements: &mut Elements) -> anyhow::Result<()> {
+    if inline.iter().all(|child| matches!(child, TagOrText::Text(text) if is_whitespace(text))) {
+        inline.clear();
+        return Ok(());
+    }
+    let mut spans = vec![];
+    tags_to_spans(std::mem::take(inline), &SpanFormat::default(), &mut spans)?;
+    elements.push(Node::new("p", Box::new(Paragraph { spans })));
+    Ok(())
+}
+
+fn get_spans(tag: Tag) -> anyhow::Result<Vec<Span>> {
+    let mut spans = vec![];
+    tags_to_spans(tag.children, &SpanFormat::default(), &mut spans)?;
+    if spans.is_empty() {
+        Err(anyhow!("Could not find text for element"))
+    } else {
+        Ok(spans)
+    }
+}
+
+fn tags_to_spans(children: Vec<TagOrText>, format: &SpanFormat, spans: &mut Vec<Span>) -> anyhow::Result<()> {
+    for child in children {
+        match child {
+            TagOrText::Text(text) => spans.push(Span { text, format: format.clone() }),
+            TagOrText::Tag(tag) => {
+                let mut format = format.clone();
+                match tag.name.as_str() {
+                    "b" => format.bold = true,
+                    "i" => format.italic = true,
+                    "u" => format.underline = true,
+                    "s" => format.strikethrough = true,
+                    "code" => format.code = true,
+                    "span" => {
+                        if let Some(color) = get_attribute(&tag, "color") {
+                            match parse_color(&color) {
+                                Some(color) => format.color = Some(color),
+                                None => return Err(anyhow!("Invalid color '{}'", color)),
+                            }
+                        }
+                    }
+                    "link" => {
+                        match get_attribute(&tag, "dst") {
+                            Some(dst) => format.link = Some(dst),
+                            None => return Err(anyhow!("No dst attribute for link")),
+                        }
+                    }
+                    _ => return Err(anyhow!("Unexpected tag '{}' in text", tag.name)),
+                }
+                tags_to_spans(tag.children, &format, spans)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_color(value: &str) -> Option<Color32> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let digit = |index: usize| u8::from_str_radix(hex.get(index..index + 1)?, 16).ok();
+        let byte = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+        return match hex.len() {
+            3 => Some(Color32::from_rgb(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 => Some(Color32::from_rgb(byte(0)?, byte(2)?, byte(4)?)),
+            8 => Some(Color32::from_rgba_unmultiplied(byte(0)?, byte(2)?, byte(4)?, byte(6)?)),
+            _ => None,
+        };
+    }
+    Some(match value {
+        "black" => Color32::BLACK,
+        "white" => Color32::WHITE,
+        "gray" | "grey" => Color32::GRAY,
+        "red" => Color32::RED,
+        "green" => Color32::GREEN,
+        "blue" => Color32::BLUE,
+        "yellow" => Color32::YELLOW,
+        "orange" => Color32::from_rgb(255, 165, 0),
+        "purple" => Color32::from_rgb(128, 0, 128),
+        "brown" => Color32::BROWN,
+        "gold" => Color32::GOLD,
+        "transparent" => Color32::TRANSPARENT,
+        _ => return None,
+    })
+}
+
+fn trim_code(text: &str) -> String {
+    let text = text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')).unwrap_or(text);
+    match text.rfind('\n') {
+        Some(pos) if is_whitespace(&text[pos..]) => text[..pos].trim_end_matches('\r').to_string(),
+        _ => text.to_string(),
+    }
+}
+
+pub fn parse_size(value: Option<String>) -> anyhow::Result<Option<f32>> {
+    match value.as_deref() {
+        None | Some("auto") => Ok(None),
+        Some(val) => match val.trim_end_matches("px").parse::<f32>() {
+            Ok(size) if size.is_finite() && size >= 0.0 => Ok(Some(size)),
+            _ => Err(anyhow!("Invalid size '{}'", val)),
+        },
+    }
+}
+
+pub fn parse_offset(value: &str) -> anyhow::Result<f32> {
+    match value.trim_end_matches("px").parse::<f32>() {
+        Ok(offset) if offset.is_finite() => Ok(offset),
+        _ => Err(anyhow!("Invalid offset '{}'", value)),
+    }
+}
+
+fn is_whitespace(text: &str) -> bool {
+    text.chars().all(|c| c == ' ' || c == '\n' || c == '\r' || c == '\t')
+}
+
 fn get_text(tag: Tag) -> anyhow::Result<String> {
-    if let Some(TagOrText::Text(text)) = tag.children.get(0) {
+    if let Some(TagOrText::Text(text)) = tag.children.first() {
         Ok(text.clone())
     } else {
         Err(anyhow!("Could not find text for element"))
@@ -407,4 +1020,116 @@ fn get_attribute(tag: &Tag, attribute: &str) -> Option<String> {
     } else {
         None
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_with_head(name: &str, head: &str, files: &[(&str, &str)]) -> anyhow::Result<Page> {
+        let root = std::env::temp_dir().join(format!("rml-parser-{}-{}", name, std::process::id()));
+        let dir = root.join("page");
+        fs::create_dir_all(&dir).unwrap();
+        for (path, text) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let path = dir.join("index.rml");
+        fs::write(&path, format!("<head><title>Test</title>{}</head><body></body>", head)).unwrap();
+        let page = parse_page(path.to_str().unwrap());
+        fs::remove_dir_all(root).unwrap();
+        page
+    }
+
+    struct DepthProbe {
+        inner: Elements,
+        depth: Rc<std::cell::Cell<Option<usize>>>,
+    }
+
+    impl Element for DepthProbe {
+        fn render(&mut self, ui: &mut Ui, _: &Style, _: &mut Executer) -> Response {
+            ui.label("")
+        }
+
+        fn children_mut(&mut self) -> Option<&mut Elements> {
+            Some(&mut self.inner)
+        }
+
+        fn set_list_depth(&mut self, depth: usize) {
+            self.depth.set(Some(depth));
+        }
+    }
+
+    fn probe(tag: &str, inner: Elements) -> (Node, Rc<std::cell::Cell<Option<usize>>>) {
+        let depth = Rc::default();
+        (Node::new(tag, Box::new(DepthProbe { inner, depth: Rc::clone(&depth) })), depth)
+    }
+
+    #[test]
+    fn counts_list_depth_through_containers() {
+        let (inner_list, inner_depth) = probe("ol", vec![]);
+        let (div, _) = probe("div", vec![inner_list]);
+        let item = Node::new("li", Box::new(ListItem { inner: vec![div] }));
+        let (outer_list, outer_depth) = probe("ul", vec![item]);
+        let mut body = vec![outer_list];
+        restyle_elements(&mut body, &Stylesheet::default(), &mut vec![], 500.0);
+        assert_eq!(outer_depth.get(), Some(0));
+        assert_eq!(inner_depth.get(), Some(1));
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size(None).unwrap(), None);
+        assert_eq!(parse_size(Some("auto".into())).unwrap(), None);
+        assert_eq!(parse_size(Some("0".into())).unwrap(), Some(0.0));
+        assert_eq!(parse_size(Some("120px".into())).unwrap(), Some(120.0));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for value in ["-1", "-10px", "NaN", "inf", "-inf", "", "wide"] {
+            assert!(parse_size(Some(value.into())).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_div_and_grid_sizes() {
+        for tag in [r#"<div width="-5"></div>"#, r#"<div max-height="NaN"></div>"#, r#"<div gap="-2"></div>"#, r#"<grid gap="inf"></grid>"#, r#"<grid min-column-width="-100"></grid>"#] {
+            assert!(parse_string(tag).is_err(), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("-12px").unwrap(), -12.0);
+        for value in ["NaN", "inf", "-inf", "left"] {
+            assert!(parse_offset(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn loads_stylesheets_inside_the_page_folder() {
+        let page = page_with_head("link-inside", r#"<link rel="stylesheet" href="style/main.css"></link>"#, &[("page/style/main.css", "p { color: red }")]);
+        assert!(page.is_ok());
+    }
+
+    #[test]
+    fn rejects_stylesheets_outside_the_page_folder() {
+        let page = page_with_head("link-outside", r#"<link rel="stylesheet" href="../main.css"></link>"#, &[("main.css", "p { color: red }")]);
+        let why = page.err().unwrap().to_string();
+        assert!(why.starts_with("Could not load stylesheet '../main.css'") && why.contains("outside of the folder"), "{}", why);
+    }
+
+    #[test]
+    fn loads_theme_files_inside_the_page_folder() {
+        let page = page_with_head("theme-inside", r#"<theme src="dark.theme"></theme>"#, &[("page/dark.theme", "base: dark")]);
+        assert!(page.unwrap().theme.is_some());
+    }
+
+    #[test]
+    fn rejects_theme_files_outside_the_page_folder() {
+        let page = page_with_head("theme-outside", r#"<theme src="../dark.theme"></theme>"#, &[("dark.theme", "base: dark")]);
+        let why = page.err().unwrap().to_string();
+        assert!(why.starts_with("Could not load theme '../dark.theme'") && why.contains("outside of the folder"), "{}", why);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{cell::Cell, path::{Path, PathBuf}, rc::Rc, time::Instant};
+use anyhow::anyhow;
+use mlua::{Debug, Error, Function, HookTriggers, Lua, LuaOptions, MultiValue, Result, StdLib, Table, Thread, Value};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Permission {
+    ReadFiles,
+    WriteFiles,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 2] = [Permission::ReadFiles, Permission::WriteFiles];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|permission| permission.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ReadFiles => "read-files",
+            Self::WriteFiles => "write-files",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::ReadFiles => "read files in its folder",
+            Self::WriteFiles => "write files in its folder",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub time_ms: u64,
+    pub memory_mb: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { time_ms: 1000, memory_mb: 64 }
+    }
+}
+
+const HOOK_INSTRUCTIONS: u32 = 1000;
+
+const SAFE_OS_FUNCTIONS: [&str; 4] = ["clock", "date", "difftime", "time"];
+
+const TEXT_ONLY_LOAD: &str = r#"
+    local load = load
+    _G.load = function(chunk, name, _, ...)
+        return load(chunk, name, "t", ...)
+    end
+"#;
+
+const WRAP_WITH_RESUME: &str = r#"
+    local create, resume, pack, unpack = coroutine.create, coroutine.resume, table.pack, table.unpack
+    coroutine.wrap = function(f)
+        local thread = create(f)
+        return function(...)
+            local results = pack(resume(thread, ...))
+            if not results[1] then
+                error(results[2], 0)
+            end
+            return unpack(results, 2, results.n)
+        end
+    end
+"#;
+
+pub fn sandboxed_lua() -> Result<Lua> {
+    let lua = Lua::new_with(StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH, LuaOptions::default())?;
+    lua.load_from_std_lib(StdLib::OS)?;
+    {
+        let globals = lua.globals();
+        let os: Table = globals.get("os")?;
+        let safe_os = lua.create_table()?;
+        for name in SAFE_OS_FUNCTIONS {
+            safe_os.set(name, os.get::<_, Function>(name)?)?;
+        }
+        globals.set("os", safe_os)?;
+        globals.set("dofile", Value::Nil)?;
+        globals.set("loadfile", Value::Nil)?;
+    }
+    lua.load(TEXT_ONLY_LOAD).set_name("sandbox").exec()?;
+    Ok(lua)
+}
+
+#[derive(Clone, Default)]
+pub struct Deadline {
+    at: Rc<Cell<Option<Instant>>>,
+    timed_out: Rc<Cell<bool>>,
+}
+
+impl Deadline {
+    pub fn set(&self, deadline: Option<Instant>) {
+        self.at.set(deadline);
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.get()
+    }
+
+    pub fn clear_timed_out(&self) {
+        self.timed_out.set(false);
+    }
+
+    fn triggers(&self) -> HookTriggers {
+        HookTriggers::new().every_nth_instruction(if self.timed_out() { 1 } else { HOOK_INSTRUCTIONS })
+    }
+
+    fn hook(&self, escalated: bool) -> impl Fn(&Lua, Debug) -> Result<()> + 'static {
+        let deadline = self.clone();
+        move |lua, _| {
+            if deadline.timed_out() || deadline.at.get().is_some_and(|at| Instant::now() > at) {
+                deadline.timed_out.set(true);
+                if !escalated {
+                    lua.set_hook(deadline.triggers(), deadline.hook(true));
+                }
+                return Err(Error::external("Script ran for too long and was stopped"));
+            }
+            if escalated {
+                lua.set_hook(deadline.triggers(), deadline.hook(false));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn watch(&self, lua: &Lua) {
+        lua.set_hook(self.triggers(), self.hook(self.timed_out()));
+    }
+
+    pub fn watch_thread(&self, thread: &Thread) {
+        thread.set_hook(self.triggers(), self.hook(self.timed_out()));
+    }
+
+    pub fn watch_coroutines(&self, lua: &Lua) -> Result<()> {
+        let deadline = self.clone();
+        let resume = lua.create_function(move |lua, (thread, args): (Thread, MultiValue)| {
+            deadline.watch_thread(&thread);
+            let result = thread.resume::<_, MultiValue>(args);
+            deadline.watch(lua);
+            if deadline.timed_out() {
+                return Err(Error::external("Script ran for too long and was stopped"));
+            }
+            let (ok, mut values) = match result {
+                Ok(values) => (true, values),
+                Err(Error::RuntimeError(message)) => {
+                    let message = message.split("\nstack traceback:").next().unwrap_or_default().to_string();
+                    (false, MultiValue::from_vec(vec![Value::String(lua.create_string(message)?)]))
+                }
+                Err(why) => (false, MultiValue::from_vec(vec![Value::String(lua.create_string(why.to_string())?)])),
+            };
+            values.push_front(Value::Boolean(ok));
+            Ok(values)
+        })?;
+        let coroutine: Table = lua.globals().get("coroutine")?;
+        coroutine.set("resume", resume)?;
+        lua.load(WRAP_WITH_RESUME).set_name("sandbox").exec()
+    }
+}
+
+pub fn resolve_path(dir: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let root = if dir.as_os_str().is_empty() { Path::new(".") } else { dir }.canonicalize()?;
+    let joined = root.join(path);
+    let resolved = match joined.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => match (joined.parent(), joined.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize()?.join(name),
+            _ => return Err(anyhow!("Invalid path '{}'", path)),
+        },
+    };
+    if !resolved.starts_with(&root) {
+        return Err(anyhow!("'{}' is outside of the folder of the page", path));
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn page_dir(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("rml-sandbox-{}-{}", name, std::process::id()));
+        let dir = root.join("page");
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data/notes.txt"), "notes").unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        (root.canonicalize().unwrap(), dir)
+    }
+
+    #[test]
+    fn resolves_files_inside_the_folder() {
+        let (root, dir) = page_dir("inside");
+        assert_eq!(resolve_path(&dir, "data/notes.txt").unwrap(), root.join("page/data/notes.txt"));
+        assert_eq!(resolve_path(&dir, "data/../data/notes.txt").unwrap(), root.join("page/data/notes.txt"));
+        assert_eq!(resolve_path(&dir, "data/new.txt").unwrap(), root.join("page/data/new.txt"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_outside_the_folder() {
+        let (root, dir) = page_dir("outside");
+        for path in ["..", "../secret.txt", "data/../../secret.txt", "../new.txt", root.join("secret.txt").to_str().unwrap()] {
+            let why = resolve_path(&dir, path).unwrap_err().to_string();
+            assert!(why.contains("outside of the folder"), "{}: {}", path, why);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_in_missing_folders() {
+        let (root, dir) = page_dir("missing");
+        assert!(resolve_path(&dir, "missing/new.txt").is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::{cell::RefCell, collections::HashMap, fs, path::{Path, PathBuf}, rc::Rc, time::SystemTime};
+use mlua::{ChunkMode, Error, Function, Lua, Result, Table, Value};
+use crate::sandbox::resolve_path;
+
+#[derive(Clone)]
+pub enum Script {
+    Inline { code: String, name: String, line: usize },
+    File(String),
+}
+
+struct Chunk {
+    modified: SystemTime,
+    bytecode: Vec<u8>,
+}
+
+#[derive(Clone, Default)]
+pub struct ChunkCache(Rc<RefCell<HashMap<PathBuf, Chunk>>>);
+
+impl ChunkCache {
+    pub fn load<'lua>(&self, lua: &'lua Lua, dir: &Path, src: &str) -> Result<Function<'lua>> {
+        let path = resolve_path(dir, src).map_err(|why| Error::external(format!("Could not load script '{}': {}", src, why)))?;
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).map_err(|why| Error::external(format!("Could not load script '{}': {}", src, why)))?;
+        let name = format!("@{}", src);
+        if let Some(chunk) = self.0.borrow().get(&path) {
+            if chunk.modified == modified {
+                return lua.load(chunk.bytecode.as_slice()).set_name(name).set_mode(ChunkMode::Binary).into_function();
+            }
+        }
+        let text = fs::read_to_string(&path).map_err(|why| Error::external(format!("Could not load script '{}': {}", src, why)))?;
+        let function = lua.load(text).set_name(name).set_mode(ChunkMode::Text).into_function()?;
+        self.0.borrow_mut().insert(path, Chunk { modified, bytecode: function.dump(false) });
+        Ok(function)
+    }
+}
+
+const LOADED_MODULES: &str = "loaded_modules";
+
+pub fn set_require(lua: &Lua, dir: PathBuf, chunks: ChunkCache) -> Result<()> {
+    lua.set_named_registry_value(LOADED_MODULES, lua.create_table()?)?;
+    let require = lua.create_function(move |lua, name: String| {
+        let loaded: Table = lua.named_registry_value(LOADED_MODULES)?;
+        match loaded.get::<_, Value>(name.as_str())? {
+            Value::Nil => {}
+            Value::Boolean(false) => return Err(Error::external(format!("Module '{}' requires itself", name))),
+            value => return Ok(value),
+        }
+        if name.is_empty() || !name.chars().all(|chr| chr.is_alphanumeric() || chr == '_' || chr == '-' || chr == '.') {
+            return Err(Error::external(format!("Invalid module name '{}'", name)));
+        }
+        let base = name.replace('.', "/");
+        let candidates = [format!("{}.lua", base), format!("{}/init.lua", base)];
+        let Some(src) = candidates.iter().find(|src| resolve_path(&dir, src).is_ok_and(|path| path.is_file())) else {
+            return Err(Error::external(format!("Module '{}' not found, tried '{}' and '{}'", name, candidates[0], candidates[1])));
+        };
+        loaded.set(name.as_str(), false)?;
+        let result = chunks.load(lua, &dir, src).and_then(|module| module.call::<_, Value>(name.as_str()));
+        let value = match result {
+            Ok(Value::Nil) => Value::Boolean(true),
+            Ok(value) => value,
+            Err(why) => {
+                loaded.set(name.as_str(), Value::Nil)?;
+                return Err(why);
+            }
+        };
+        loaded.set(name.as_str(), value.clone())?;
+        Ok(value)
+    })?;
+    lua.globals().set("require", require)
+}
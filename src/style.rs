@@ -0,0 +1,564 @@
+use anyhow::anyhow;
+use eframe::egui::{Align, Color32, Direction, FontFamily, FontId, Frame, RichText, Stroke, TextStyle, Ui};
+use crate::parser::parse_color;
+
+const MIN_FONT_SIZE: f32 = 1.0;
+const MAX_FONT_SIZE: f32 = 512.0;
+
+#[derive(Default, Clone, PartialEq)]
+pub struct Style {
+    pub color: Option<Color32>,
+    pub background: Option<Color32>,
+    pub font_size: Option<f32>,
+    pub font_family: Option<FontFamily>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub padding: Option<f32>,
+    pub margin: Option<f32>,
+    pub border: Option<Stroke>,
+    pub corner_radius: Option<f32>,
+    pub direction: Option<Direction>,
+    pub align: Option<Align>,
+    pub hidden: Option<bool>,
+}
+
+impl Style {
+    pub fn parse(declarations: &str) -> anyhow::Result<Self> {
+        let mut style = Self::default();
+        for declaration in declarations.split(';') {
+            if declaration.trim().is_empty() {
+                continue;
+            }
+            let Some((property, value)) = declaration.split_once(':') else {
+                return Err(anyhow!("Invalid style declaration '{}'", declaration.trim()));
+            };
+            style.set_property(property.trim(), value.trim())?;
+        }
+        Ok(style)
+    }
+
+    fn set_property(&mut self, property: &str, value: &str) -> anyhow::Result<()> {
+        match property {
+            "color" => self.color = Some(parse_style_color(value)?),
+            "background" | "background-color" => self.background = Some(parse_style_color(value)?),
+            "font-size" => self.font_size = Some(parse_font_size(value)?),
+            "font-family" => {
+                self.font_family = Some(match value {
+                    "proportional" | "sans-serif" => FontFamily::Proportional,
+                    "monospace" => FontFamily::Monospace,
+                    name => FontFamily::Name(name.trim_matches(['"', '\'']).into()),
+                })
+            }
+            "font-weight" => {
+                self.bold = Some(match value {
+                    "bold" => true,
+                    "normal" => false,
+                    _ => return Err(anyhow!("Invalid font-weight '{}'", value)),
+                })
+            }
+            "font-style" => {
+                self.italic = Some(match value {
+                    "italic" => true,
+                    "normal" => false,
+                    _ => return Err(anyhow!("Invalid font-style '{}'", value)),
+                })
+            }
+            "padding" => self.padding = Some(parse_number(value)?),
+            "margin" => self.margin = Some(parse_number(value)?),
+            "border" => {
+                let mut width = 1.0;
+                let mut color = Color32::GRAY;
+                for part in value.split_whitespace() {
+                    if let Ok(number) = parse_number(part) {
+                        width = number;
+                    } else if part != "solid" {
+                        color = parse_style_color(part)?;
+                    }
+                }
+                self.border = Some(Stroke::new(width, color));
+            }
+            "border-radius" | "corner-radius" => self.corner_radius = Some(parse_number(value)?),
+            "direction" => {
+                self.direction = Some(match value {
+                    "down" => Direction::TopDown,
+                    "up" => Direction::BottomUp,
+                    "left" => Direction::RightToLeft,
+                    "right" => Direction::LeftToRight,
+                    _ => return Err(anyhow!("Invalid direction '{}'", value)),
+                })
+            }
+            "align" => {
+                self.align = Some(match value {
+                    "min" => Align::Min,
+                    "center" => Align::Center,
+                    "max" => Align::Max,
+                    _ => return Err(anyhow!("Invalid align '{}'", value)),
+                })
+            }
+            "display" => {
+                self.hidden = Some(match value {
+                    "none" => true,
+                    "block" => false,
+                    _ => return Err(anyhow!("Invalid display '{}'", value)),
+                })
+            }
+            _ => return Err(anyhow!("Unknown style property '{}'", property)),
+        }
+        Ok(())
+    }
+
+    pub fn declarations(&self) -> String {
+        let mut declarations = vec![];
+        if let Some(color) = self.color {
+            declarations.push(format!("color: {}", color_name(color)));
+        }
+        if let Some(background) = self.background {
+            declarations.push(format!("background: {}", color_name(background)));
+        }
+        if let Some(font_size) = self.font_size {
+            declarations.push(format!("font-size: {}", font_size));
+        }
+        if let Some(font_family) = &self.font_family {
+            let name = match font_family {
+                FontFamily::Proportional => "proportional",
+                FontFamily::Monospace => "monospace",
+                FontFamily::Name(name) => name,
+            };
+            declarations.push(format!("font-family: {}", name));
+        }
+        if let Some(bold) = self.bold {
+            declarations.push(format!("font-weight: {}", if bold { "bold" } else { "normal" }));
+        }
+        if let Some(italic) = self.italic {
+            declarations.push(format!("font-style: {}", if italic { "italic" } else { "normal" }));
+        }
+        if let Some(padding) = self.padding {
+            declarations.push(format!("padding: {}", padding));
+        }
+        if let Some(margin) = self.margin {
+            declarations.push(format!("margin: {}", margin));
+        }
+        if let Some(border) = self.border {
+            declarations.push(format!("border: {} {}", border.width, color_name(border.color)));
+        }
+        if let Some(corner_radius) = self.corner_radius {
+            declarations.push(format!("border-radius: {}", corner_radius));
+        }
+        if let Some(direction) = self.direction {
+            let name = match direction {
+                Direction::TopDown => "down",
+                Direction::BottomUp => "up",
+                Direction::RightToLeft => "left",
+                Direction::LeftToRight => "right",
+            };
+            declarations.push(format!("direction: {}", name));
+        }
+        if let Some(align) = self.align {
+            let name = match align {
+                Align::Min => "min",
+                Align::Center => "center",
+                Align::Max => "max",
+            };
+            declarations.push(format!("align: {}", name));
+        }
+        if let Some(hidden) = self.hidden {
+            declarations.push(format!("display: {}", if hidden { "none" } else { "block" }));
+        }
+        declarations.join("; ")
+    }
+
+    pub fn merge(&mut self, other: &Style) {
+        self.color = other.color.or(self.color);
+        self.background = other.background.or(self.background);
+        self.font_size = other.font_size.or(self.font_size);
+        self.font_family = other.font_family.clone().or_else(|| self.font_family.clone());
+        self.bold = other.bold.or(self.bold);
+        self.italic = other.italic.or(self.italic);
+        self.padding = other.padding.or(self.padding);
+        self.margin = other.margin.or(self.margin);
+        self.border = other.border.or(self.border);
+        self.corner_radius = other.corner_radius.or(self.corner_radius);
+        self.direction = other.direction.or(self.direction);
+        self.align = other.align.or(self.align);
+        self.hidden = other.hidden.or(self.hidden);
+    }
+
+    pub fn inherit(&self, parent: &Style) -> Style {
+        Style {
+            color: self.color.or(parent.color),
+            font_size: self.font_size.or(parent.font_size),
+            font_family: self.font_family.clone().or_else(|| parent.font_family.clone()),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            ..self.clone()
+        }
+    }
+
+    pub fn frame(&self) -> Option<Frame> {
+        if self.background.is_none() && self.padding.is_none() && self.margin.is_none() && self.border.is_none() && self.corner_radius.is_none() {
+            return None;
+        }
+        Some(Frame::none()
+            .fill(self.background.unwrap_or(Color32::TRANSPARENT))
+            .inner_margin(self.padding.unwrap_or(0.0))
+            .outer_margin(self.margin.unwrap_or(0.0))
+            .stroke(self.border.unwrap_or(Stroke::NONE))
+            .rounding(self.corner_radius.unwrap_or(0.0)))
+    }
+
+    pub fn has_box(&self) -> bool {
+        self.background.is_some() || self.border.is_some()
+    }
+
+    pub fn font_id(&self, ui: &Ui, text_style: TextStyle) -> FontId {
+        let default = text_style.resolve(ui.style());
+        let family = match &self.font_family {
+            Some(family @ FontFamily::Name(_)) if !ui.fonts(|fonts| fonts.families().contains(family)) => default.family,
+            Some(family) => family.clone(),
+            None => default.family,
+        };
+        FontId::new(self.font_size_or(default.size), family)
+    }
+
+    pub fn font_size_or(&self, default: f32) -> f32 {
+        self.font_size.filter(|size| size.is_finite()).unwrap_or(default).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE)
+    }
+
+    pub fn rich_text(&self, text: &str, ui: &Ui, text_style: TextStyle) -> RichText {
+        let mut rich = RichText::new(text).font(self.font_id(ui, text_style));
+        if self.bold == Some(true) {
+            rich = rich.strong();
+        }
+        if self.italic == Some(true) {
+            rich = rich.italics();
+        }
+        if let Some(color) = self.color {
+            rich = rich.color(color);
+        }
+        rich
+    }
+}
+
+fn color_name(color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    if a == 255 {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+pub fn parse_number(value: &str) -> anyhow::Result<f32> {
+    match value.trim_end_matches("px").parse::<f32>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => Ok(number),
+        _ => Err(anyhow!("Invalid number '{}'", value)),
+    }
+}
+
+fn parse_font_size(value: &str) -> anyhow::Result<f32> {
+    match parse_number(value) {
+        Ok(size) if size > 0.0 => Ok(size),
+        _ => Err(anyhow!("Invalid font-size '{}'", value)),
+    }
+}
+
+pub fn parse_style_color(value: &str) -> anyhow::Result<Color32> {
+    parse_color(value).ok_or_else(|| anyhow!("Invalid color '{}'", value))
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct PseudoState {
+    pub hover: bool,
+    pub active: bool,
+    pub focus: bool,
+    pub disabled: bool,
+}
+
+impl PseudoState {
+    fn contains(&self, required: &PseudoState) -> bool {
+        (self.hover || !required.hover)
+            && (self.active || !required.active)
+            && (self.focus || !required.focus)
+            && (self.disabled || !required.disabled)
+    }
+
+    fn count(&self) -> usize {
+        self.hover as usize + self.active as usize + self.focus as usize + self.disabled as usize
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Selectable {
+    pub tag: String,
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+}
+
+#[derive(Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    pseudo: PseudoState,
+}
+
+impl Compound {
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut compound = Self::default();
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('*') {
+                rest = after;
+                continue;
+            }
+            let prefix = rest.chars().next().unwrap();
+            let body = if prefix == '.' || prefix == '#' || prefix == ':' { &rest[1..] } else { rest };
+            let len = body.find(['.', '#', ':']).unwrap_or(body.len());
+            let name = &body[..len];
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+                return Err(anyhow!("Invalid selector '{}'", text));
+            }
+            match prefix {
+                '.' => compound.classes.push(name.to_string()),
+                '#' => compound.id = Some(name.to_string()),
+                ':' => match name {
+                    "hover" => compound.pseudo.hover = true,
+                    "active" => compound.pseudo.active = true,
+                    "focus" => compound.pseudo.focus = true,
+                    "disabled" => compound.pseudo.disabled = true,
+                    _ => return Err(anyhow!("Unknown pseudo-class ':{}'", name)),
+                },
+                _ => compound.tag = Some(name.to_string()),
+            }
+            rest = &body[len..];
+        }
+        Ok(compound)
+    }
+
+    fn matches(&self, element: &Selectable) -> bool {
+        self.tag.as_ref().is_none_or(|tag| *tag == element.tag)
+            && self.id.as_ref().is_none_or(|id| element.id.as_ref() == Some(id))
+            && self.classes.iter().all(|class| element.classes.contains(class))
+    }
+}
+
+struct Selector {
+    compounds: Vec<Compound>,
+}
+
+impl Selector {
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let compounds = text.split_whitespace().map(Compound::parse).collect::<anyhow::Result<Vec<_>>>()?;
+        if compounds.is_empty() {
+            return Err(anyhow!("Empty selector"));
+        }
+        Ok(Self { compounds })
+    }
+
+    fn specificity(&self) -> (usize, usize, usize) {
+        self.compounds.iter().fold((0, 0, 0), |(ids, classes, tags), compound| {
+            (ids + compound.id.is_some() as usize, classes + compound.classes.len() + compound.pseudo.count(), tags + compound.tag.is_some() as usize)
+        })
+    }
+
+    fn pseudo(&self) -> PseudoState {
+        self.compounds.last().unwrap().pseudo
+    }
+
+    fn matches(&self, element: &Selectable, ancestors: &[Selectable]) -> bool {
+        let (subject, rest) = self.compounds.split_last().unwrap();
+        if !subject.matches(element) {
+            return false;
+        }
+        let mut ancestors = ancestors.iter().rev();
+        rest.iter().rev().all(|compound| compound.pseudo == PseudoState::default() && ancestors.any(|ancestor| compound.matches(ancestor)))
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Media {
+    min_width: Option<f32>,
+    max_width: Option<f32>,
+}
+
+impl Media {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut media = Self::default();
+        for condition in text.split(" and ") {
+            let condition = condition.trim().trim_start_matches('(').trim_end_matches(')');
+            let Some((feature, value)) = condition.split_once(':') else {
+                return Err(anyhow!("Invalid media condition '{}'", condition));
+            };
+            match feature.trim() {
+                "min-width" => media.min_width = Some(parse_number(value.trim())?),
+                "max-width" => media.max_width = Some(parse_number(value.trim())?),
+                feature => return Err(anyhow!("Unknown media feature '{}'", feature)),
+            }
+        }
+        Ok(media)
+    }
+
+    fn matches(&self, width: f32) -> bool {
+        self.min_width.is_none_or(|min_width| width >= min_width) && self.max_width.is_none_or(|max_width| width <= max_width)
+    }
+}
+
+struct Rule {
+    selector: Selector,
+    style: Style,
+    media: Option<Media>,
+}
+
+#[derive(Default)]
+pub struct Stylesheet {
+    rules: Vec<Rule>,
+}
+
+impl Stylesheet {
+    pub fn parse(&mut self, text: &str, media: Option<Media>) -> anyhow::Result<()> {
+        let mut text = text.to_string();
+        while let Some(start) = text.find("/*") {
+            let end = text[start..].find("*/").map_or(text.len(), |end| start + end + 2);
+            text.replace_range(start..end, "");
+        }
+        for block in text.split('}') {
+            if block.trim().is_empty() {
+                continue;
+            }
+            let Some((selectors, declarations)) = block.split_once('{') else {
+                return Err(anyhow!("Invalid style rule '{}'", block.trim()));
+            };
+            let style = Style::parse(declarations)?;
+            for selector in selectors.split(',') {
+                self.rules.push(Rule { selector: Selector::parse(selector)?, style: style.clone(), media });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn media_state(&self, width: f32) -> Vec<bool> {
+        self.rules.iter().filter_map(|rule| rule.media).map(|media| media.matches(width)).collect()
+    }
+
+    pub fn rules_for(&self, element: &Selectable, ancestors: &[Selectable], width: f32) -> Vec<StateRule> {
+        let mut matched: Vec<&Rule> = self.rules.iter()
+            .filter(|rule| rule.media.is_none_or(|media| media.matches(width)) && rule.selector.matches(element, ancestors))
+            .collect();
+        matched.sort_by_key(|rule| rule.selector.specificity());
+        matched.into_iter().map(|rule| StateRule { pseudo: rule.selector.pseudo(), style: rule.style.clone() }).collect()
+    }
+}
+
+pub struct StateRule {
+    pseudo: PseudoState,
+    style: Style,
+}
+
+pub fn style_for_state(rules: &[StateRule], inline: &Style, state: PseudoState) -> Style {
+    let mut style = Style::default();
+    for rule in rules {
+        if state.contains(&rule.pseudo) {
+            style.merge(&rule.style);
+        }
+    }
+    style.merge(inline);
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("12").unwrap(), 12.0);
+        assert_eq!(parse_number("1.5px").unwrap(), 1.5);
+        assert_eq!(parse_number("0").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        for value in ["-1", "-0.5px", "NaN", "inf", "-inf", "infinity", "", "px", "ten"] {
+            assert!(parse_number(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_font_sizes() {
+        for value in ["0", "0px", "-3", "NaN", "inf"] {
+            assert!(Style::parse(&format!("font-size: {}", value)).is_err(), "{}", value);
+        }
+        assert_eq!(Style::parse("font-size: 20px").unwrap().font_size, Some(20.0));
+    }
+
+    #[test]
+    fn rejects_negative_box_sizes() {
+        for declaration in ["padding: -1", "margin: NaN", "border-radius: -4", "border: -2 red"] {
+            assert!(Style::parse(declaration).is_err(), "{}", declaration);
+        }
+    }
+
+    #[test]
+    fn clamps_font_size() {
+        let style = |font_size| Style { font_size, ..Style::default() };
+        assert_eq!(style(None).font_size_or(14.0), 14.0);
+        assert_eq!(style(Some(0.0)).font_size_or(14.0), MIN_FONT_SIZE);
+        assert_eq!(style(Some(-5.0)).font_size_or(14.0), MIN_FONT_SIZE);
+        assert_eq!(style(Some(f32::NAN)).font_size_or(14.0), 14.0);
+        assert_eq!(style(Some(f32::INFINITY)).font_size_or(14.0), 14.0);
+        assert_eq!(style(Some(1e9)).font_size_or(14.0), MAX_FONT_SIZE);
+    }
+
+    fn selectable(tag: &str, id: Option<&str>, classes: &[&str]) -> Selectable {
+        Selectable { tag: tag.to_string(), id: id.map(str::to_string), classes: classes.iter().map(|class| class.to_string()).collect() }
+    }
+
+    fn color_for(css: &str, element: &Selectable, ancestors: &[Selectable], state: PseudoState) -> Option<Color32> {
+        let mut stylesheet = Stylesheet::default();
+        stylesheet.parse(css, None).unwrap();
+        style_for_state(&stylesheet.rules_for(element, ancestors, 500.0), &Style::default(), state).color
+    }
+
+    #[test]
+    fn counts_specificity() {
+        assert_eq!(Selector::parse("p").unwrap().specificity(), (0, 0, 1));
+        assert_eq!(Selector::parse("div p.note").unwrap().specificity(), (0, 1, 2));
+        assert_eq!(Selector::parse("#main .note:hover").unwrap().specificity(), (1, 2, 0));
+        assert_eq!(Selector::parse("*").unwrap().specificity(), (0, 0, 0));
+        assert!(Selector::parse("#a").unwrap().specificity() > Selector::parse("p.a.b.c").unwrap().specificity());
+    }
+
+    #[test]
+    fn matches_compounds() {
+        let element = selectable("p", Some("intro"), &["note", "big"]);
+        for text in ["p", "*", "#intro", ".note", ".big.note", "p#intro.note"] {
+            assert!(Selector::parse(text).unwrap().matches(&element, &[]), "{}", text);
+        }
+        for text in ["h", "#other", ".small", "p.note.small"] {
+            assert!(!Selector::parse(text).unwrap().matches(&element, &[]), "{}", text);
+        }
+    }
+
+    #[test]
+    fn matches_descendants() {
+        let element = selectable("p", None, &[]);
+        let ancestors = [selectable("div", Some("main"), &[]), selectable("li", None, &["item"])];
+        for text in ["div p", "#main p", "div .item p", "div li p"] {
+            assert!(Selector::parse(text).unwrap().matches(&element, &ancestors), "{}", text);
+        }
+        for text in ["ul p", ".item div p", "li div p", "div:hover p"] {
+            assert!(!Selector::parse(text).unwrap().matches(&element, &ancestors), "{}", text);
+        }
+    }
+
+    #[test]
+    fn more_specific_rules_win() {
+        let element = selectable("p", Some("intro"), &["note"]);
+        let red = Some(Color32::from_rgb(255, 0, 0));
+        assert_eq!(color_for("#intro { color: red } .note { color: blue } p { color: green }", &element, &[], PseudoState::default()), red);
+        assert_eq!(color_for("p { color: blue } p { color: red }", &element, &[], PseudoState::default()), red);
+        let hover = PseudoState { hover: true, ..PseudoState::default() };
+        assert_eq!(color_for("p:hover { color: red } p { color: blue }", &element, &[], hover), red);
+        assert_ne!(color_for("p:hover { color: red } p { color: blue }", &element, &[], PseudoState::default()), red);
+    }
+}
@@ -0,0 +1,88 @@
+use eframe::egui::{text::LayoutJob, Color32, CursorIcon, FontId, Response, Sense, Stroke, TextFormat, TextStyle, Ui};
+use crate::{lua::{DocumentChange, Executer}, style::Style};
+
+#[derive(Default, Clone)]
+pub struct SpanFormat {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub color: Option<Color32>,
+    pub link: Option<String>,
+}
+
+impl SpanFormat {
+    fn to_text_format(&self, ui: &Ui, style: &Style, font_id: FontId) -> TextFormat {
+        let visuals = ui.visuals();
+        let bold = self.bold || style.bold == Some(true);
+        let color = if let Some(color) = self.color {
+            color
+        } else if self.link.is_some() {
+            visuals.hyperlink_color
+        } else if let Some(color) = style.color {
+            color
+        } else if bold {
+            visuals.strong_text_color()
+        } else {
+            visuals.text_color()
+        };
+        let font_id = if self.code {
+            FontId::monospace(font_id.size)
+        } else {
+            font_id
+        };
+        TextFormat {
+            font_id,
+            color,
+            background: if self.code { visuals.code_bg_color } else { Color32::TRANSPARENT },
+            italics: self.italic || style.italic == Some(true),
+            underline: if self.underline { Stroke::new(1.0, color) } else { Stroke::NONE },
+            strikethrough: if self.strikethrough { Stroke::new(1.0, color) } else { Stroke::NONE },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Span {
+    pub text: String,
+    pub format: SpanFormat,
+}
+
+impl Span {
+    pub fn plain(text: String) -> Self {
+        Self { text, format: SpanFormat::default() }
+    }
+}
+
+pub fn render_spans(ui: &mut Ui, spans: &[Span], style: &Style, text_style: TextStyle, executer: &mut Executer) -> Response {
+    let font_id = style.font_id(ui, text_style);
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = ui.available_width();
+    for span in spans {
+        job.append(&span.text, 0.0, span.format.to_text_format(ui, style, font_id.clone()));
+    }
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let sense = if spans.iter().any(|span| span.format.link.is_some()) { Sense::click() } else { Sense::hover() };
+    let (rect, response) = ui.allocate_exact_size(galley.size(), sense);
+
+    let hovered_link = response.hover_pos().and_then(|pos| {
+        let pos = (pos - rect.min).to_pos2();
+        galley.rows.iter()
+            .filter(|row| row.rect.contains(pos))
+            .flat_map(|row| row.glyphs.iter())
+            .find(|glyph| glyph.logical_rect().contains(pos))
+            .and_then(|glyph| spans.get(glyph.section_index as usize))
+            .and_then(|span| span.format.link.clone())
+    });
+    if let Some(dst) = hovered_link {
+        ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+        if response.clicked() {
+            executer.send_change(DocumentChange::SetLocation(dst));
+        }
+    }
+
+    ui.painter().galley(rect.min, galley, ui.visuals().text_color());
+    response
+}
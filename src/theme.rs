@@ -0,0 +1,124 @@
+use anyhow::anyhow;
+use eframe::egui::{style::{WidgetVisuals, Widgets}, Rounding, Visuals};
+use crate::style::{parse_number, parse_style_color};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ThemeChoice {
+    Page,
+    System,
+    Light,
+    Dark,
+}
+
+impl ThemeChoice {
+    pub const ALL: [ThemeChoice; 4] = [ThemeChoice::Page, ThemeChoice::System, ThemeChoice::Light, ThemeChoice::Dark];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThemeChoice::Page => "Page",
+            ThemeChoice::System => "System",
+            ThemeChoice::Light => "Light",
+            ThemeChoice::Dark => "Dark",
+        }
+    }
+}
+
+pub fn builtin_theme(name: &str) -> Option<Visuals> {
+    match name {
+        "light" => Some(Visuals::light()),
+        "dark" => Some(Visuals::dark()),
+        _ => None,
+    }
+}
+
+pub fn parse_theme(text: &str) -> anyhow::Result<Visuals> {
+    let mut text = text.to_string();
+    while let Some(start) = text.find("/*") {
+        let end = text[start..].find("*/").map_or(text.len(), |end| start + end + 2);
+        text.replace_range(start..end, "");
+    }
+    let mut declarations = vec![];
+    for declaration in text.split([';', '\n']) {
+        if declaration.trim().is_empty() {
+            continue;
+        }
+        let Some((property, value)) = declaration.split_once(':') else {
+            return Err(anyhow!("Invalid theme declaration '{}'", declaration.trim()));
+        };
+        declarations.push((property.trim(), value.trim()));
+    }
+    let mut visuals = match declarations.iter().find(|(property, _)| *property == "base") {
+        Some((_, base)) => builtin_theme(base).ok_or_else(|| anyhow!("Invalid theme base '{}'", base))?,
+        None => Visuals::light(),
+    };
+    for (property, value) in declarations {
+        set_theme_property(&mut visuals, property, value)?;
+    }
+    Ok(visuals)
+}
+
+fn set_theme_property(visuals: &mut Visuals, property: &str, value: &str) -> anyhow::Result<()> {
+    let widgets = &mut visuals.widgets;
+    match property {
+        "base" => {}
+        "text-color" => visuals.override_text_color = Some(parse_style_color(value)?),
+        "link-color" => visuals.hyperlink_color = parse_style_color(value)?,
+        "background" => visuals.panel_fill = parse_style_color(value)?,
+        "window-background" => visuals.window_fill = parse_style_color(value)?,
+        "faint-background" => visuals.faint_bg_color = parse_style_color(value)?,
+        "extreme-background" => visuals.extreme_bg_color = parse_style_color(value)?,
+        "code-background" => visuals.code_bg_color = parse_style_color(value)?,
+        "selection-color" => visuals.selection.bg_fill = parse_style_color(value)?,
+        "warn-color" => visuals.warn_fg_color = parse_style_color(value)?,
+        "error-color" => visuals.error_fg_color = parse_style_color(value)?,
+        "widget-background" => {
+            let color = parse_style_color(value)?;
+            widgets.inactive.bg_fill = color;
+            widgets.inactive.weak_bg_fill = color;
+        }
+        "widget-hover-background" => {
+            let color = parse_style_color(value)?;
+            widgets.hovered.bg_fill = color;
+            widgets.hovered.weak_bg_fill = color;
+        }
+        "widget-active-background" => {
+            let color = parse_style_color(value)?;
+            widgets.active.bg_fill = color;
+            widgets.active.weak_bg_fill = color;
+        }
+        "border-color" => {
+            let color = parse_style_color(value)?;
+            widgets.noninteractive.bg_stroke.color = color;
+            widgets.inactive.bg_stroke.color = color;
+            visuals.window_stroke.color = color;
+        }
+        "border-width" => {
+            let width = parse_number(value)?;
+            for state in widget_states(widgets) {
+                state.bg_stroke.width = width;
+            }
+            visuals.window_stroke.width = width;
+        }
+        "text-width" => {
+            let width = parse_number(value)?;
+            for state in widget_states(widgets) {
+                state.fg_stroke.width = width;
+            }
+        }
+        "rounding" => {
+            let rounding = Rounding::same(parse_number(value)?);
+            for state in widget_states(widgets) {
+                state.rounding = rounding;
+            }
+            visuals.window_rounding = rounding;
+            visuals.menu_rounding = rounding;
+        }
+        "window-rounding" => visuals.window_rounding = Rounding::same(parse_number(value)?),
+        _ => return Err(anyhow!("Unknown theme property '{}'", property)),
+    }
+    Ok(())
+}
+
+fn widget_states(widgets: &mut Widgets) -> [&mut WidgetVisuals; 5] {
+    [&mut widgets.noninteractive, &mut widgets.inactive, &mut widgets.hovered, &mut widgets.active, &mut widgets.open]
+}
@@ -0,0 +1,2 @@
+local util = require("test_util")
+document:log(util.greet("test.lua"))
@@ -0,0 +1,7 @@
+local util = {}
+
+function util.greet(name)
+    return "Hello from " .. name
+end
+
+return util